@@ -0,0 +1,158 @@
+// Opening books in the Polyglot `.bin` format
+// http://hgm.nubati.net/book_format.html
+
+use crate::chess::{Board, Move};
+use crate::prng::PRNG;
+
+mod polyglot;
+
+pub use polyglot::PolyglotHasher;
+
+// Each entry is a big-endian key, move, weight and learn value
+const ENTRY_SIZE: usize = 16;
+
+#[derive(Debug, Clone, Copy)]
+struct BookEntry {
+    key: u64,
+    mv: u16,
+    weight: u16
+}
+
+pub struct Book {
+    // Sorted by key, as Polyglot writes them
+    entries: Vec<BookEntry>
+}
+
+impl Book {
+    pub fn open(path: &str) -> std::io::Result<Self> {
+        Ok(Self::from_bytes(&std::fs::read(path)?))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        // A truncated last entry is ignored
+        let mut entries = bytes.chunks_exact(ENTRY_SIZE).map(|entry| BookEntry {
+            key: u64::from_be_bytes(entry[0..8].try_into().unwrap()),
+            mv: u16::from_be_bytes(entry[8..10].try_into().unwrap()),
+            weight: u16::from_be_bytes(entry[10..12].try_into().unwrap())
+        }).collect::<Vec<_>>();
+        // Keeps the book's own order for entries with the same key
+        entries.sort_by_key(|entry| entry.key);
+        Self { entries }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn moves(&self, board: &Board) -> Vec<(Move, u16)> {
+        // The book's legal moves for `board` with their weights, skipping any that don't decode to a legal move
+        let key = PolyglotHasher::hash(board);
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        self.entries[start..].iter()
+            .take_while(|entry| entry.key == key)
+            .filter_map(|entry| Some((board.legal_move_from_uci(&polyglot_move_uci(entry.mv))?, entry.weight)))
+            .collect()
+    }
+
+    pub fn pick(&self, board: &Board, seed: u64) -> Option<Move> {
+        // A random book move, each chosen in proportion to its weight. Moves weighted 0 are never played
+        let moves = self.moves(board);
+        let total = moves.iter().map(|&(_, weight)| weight as u64).sum::<u64>();
+        if total == 0 { return None; }
+
+        let mut choice = PRNG::new(seed as u128).next() % total;
+        for (mv, weight) in moves {
+            if choice < weight as u64 {
+                return Some(mv);
+            }
+            choice -= weight as u64;
+        }
+        unreachable!()
+    }
+}
+
+fn polyglot_move_uci(mv: u16) -> String {
+    // From low to high, 3 bits each: to file, to row, from file, from row, promotion piece.
+    // Castling is written as the king taking its own rook, which `Move::from_uci` reads as a castle
+    let square = |bits: u16| format!("{}{}", (b'a' + (bits & 7) as u8) as char, (b'1' + (bits >> 3 & 7) as u8) as char);
+    let promotion = match mv >> 12 & 7 {
+        1 => "n",
+        2 => "b",
+        3 => "r",
+        4 => "q",
+        _ => ""
+    };
+    format!("{}{}{}", square(mv >> 6), square(mv), promotion)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::{apply_uci_moves, init_magic_tables};
+
+    #[test]
+    fn polyglot_hashes() {
+        init_magic_tables();
+        // The reference positions from the Polyglot book format description
+        let board = Board::default();
+        assert_eq!(PolyglotHasher::hash(&board), 0x463b96181691fc9c);
+        for (moves, hash) in [
+            (&["e2e4"][..], 0x823c9b50fd114196),
+            (&["e2e4", "d7d5"], 0x0756b94461c50fb0),
+            (&["e2e4", "d7d5", "e4e5"], 0x662fafb965db29d4),
+            (&["e2e4", "d7d5", "e4e5", "f7f5"], 0x22a48b5a8e47ff78),
+            (&["e2e4", "d7d5", "e4e5", "f7f5", "e1e2"], 0x652a607ca3f242c1),
+            (&["e2e4", "d7d5", "e4e5", "f7f5", "e1e2", "e8f7"], 0x00fdd303c946bdd9),
+            (&["a2a4", "b7b5", "h2h4", "b5b4", "c2c4"], 0x3c8123ea7b067637),
+            (&["a2a4", "b7b5", "h2h4", "b5b4", "c2c4", "b4c3", "a1a3"], 0x5c3f9b829b279560),
+        ] {
+            assert_eq!(PolyglotHasher::hash(&apply_uci_moves(&board, moves).unwrap()), hash, "{:?}", moves);
+        }
+    }
+
+    fn entry(key: u64, from: &str, to: &str, promotion: u16, weight: u16) -> [u8; ENTRY_SIZE] {
+        let square = |san: &str| (san.as_bytes()[1] - b'1') as u16 * 8 + (san.as_bytes()[0] - b'a') as u16;
+        let mv = promotion << 12 | square(from) << 6 | square(to);
+        let mut bytes = [0; ENTRY_SIZE];
+        bytes[0..8].copy_from_slice(&key.to_be_bytes());
+        bytes[8..10].copy_from_slice(&mv.to_be_bytes());
+        bytes[10..12].copy_from_slice(&weight.to_be_bytes());
+        bytes
+    }
+
+    #[test]
+    fn book_moves() {
+        init_magic_tables();
+        let start = Board::default();
+        let castling = Board::new("r3k2r/pppq1ppp/2npbn2/2b1p3/2B1P3/2NPBN2/PPPQ1PPP/R3K2R w KQkq - 6 8").unwrap();
+        let promotion = Board::new("8/1P4k1/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        let bytes = [
+            entry(PolyglotHasher::hash(&castling), "e1", "h1", 0, 3),
+            entry(PolyglotHasher::hash(&start), "e2", "e4", 0, 2),
+            entry(PolyglotHasher::hash(&start), "d2", "d4", 0, 1),
+            entry(PolyglotHasher::hash(&start), "g1", "f4", 0, 5),
+            entry(PolyglotHasher::hash(&start), "b1", "c3", 0, 0),
+            entry(PolyglotHasher::hash(&promotion), "b7", "b8", 3, 1),
+        ].concat();
+        let book = Book::from_bytes(&bytes);
+        assert_eq!(book.len(), 6);
+
+        // The illegal g1f4 is dropped, and b1c3 is in the book but never played
+        let uci = |moves: Vec<(Move, u16)>| moves.into_iter().map(|(mv, weight)| (mv.uci(), weight)).collect::<Vec<_>>();
+        assert_eq!(uci(book.moves(&start)), [("e2e4".to_owned(), 2), ("d2d4".to_owned(), 1), ("b1c3".to_owned(), 0)]);
+        for seed in 0..20 {
+            assert!(matches!(book.pick(&start, seed).map(|mv| mv.uci()).as_deref(), Some("e2e4" | "d2d4")));
+        }
+
+        // Castling is stored as the king taking its rook, and promotions carry the piece
+        assert_eq!(book.pick(&castling, 0), castling.legal_move_from_uci("e1g1"));
+        assert_eq!(book.pick(&promotion, 0), promotion.legal_move_from_uci("b7b8r"));
+        assert_eq!(book.pick(&apply_uci_moves(&start, &["e2e4"]).unwrap(), 0), None);
+    }
+}
@@ -1,13 +1,20 @@
+mod attacks;
 mod bitboard;
 mod board;
 mod color;
+mod epd;
+mod game;
 mod magic_tables;
 mod mv;
 mod piece;
 mod square;
 
-pub use board::{Board, START_POS_FEN, make_move, gen_legal_moves};
+pub use attacks::{knight_attacks, king_attacks, pawn_attacks, BETWEEN, LINE};
+pub use bitboard::Bitboard;
+pub use board::{Board, BoardState, Castle, Castles, FenError, MoveUndoer, START_POS_FEN, make_move, make_move_capturing, apply_uci_moves, gen_legal_moves, legal_moves_from, gen_captures, gen_piece_attacks, attackers_to};
 pub use color::*;
+pub use epd::{Epd, parse_epd};
+pub use game::Game;
 pub use magic_tables::init_magic_tables;
 pub use mv::*;
 pub use piece::*;
@@ -0,0 +1,261 @@
+// Attack tables for the non-sliding pieces, and lines between squares.
+// Sliding piece attacks are in `magic_tables`.
+
+use super::bitboard::Bitboard;
+use super::color::*;
+use super::square::*;
+
+#[inline]
+pub const fn knight_attacks(square: Square) -> Bitboard {
+    KNIGHT_MOVES[square.idx()]
+}
+
+#[inline]
+pub const fn king_attacks(square: Square) -> Bitboard {
+    KING_MOVES[square.idx()]
+}
+
+#[inline]
+pub const fn pawn_attacks(square: Square, color: Color) -> Bitboard {
+    // The squares a pawn of `color` on `square` captures on
+    PAWN_ATTACKS[color.idx()][square.idx()]
+}
+
+const KNIGHT_MOVES: [Bitboard; NUM_SQUARES] = {
+    let mut knight_moves = [Bitboard::EMPTY; NUM_SQUARES];
+    let mut square_idx = 0;
+    while square_idx < NUM_SQUARES {
+        let square = Square::from_idx(square_idx);
+        let mut moves = Bitboard::EMPTY;
+
+        if let Some(step) = square.up() { if let Some(step) = step.up() { if let Some(sq) = step.left() {
+            moves.0 |= Bitboard::from_square(sq).0;
+        }}}
+        if let Some(step) = square.up() { if let Some(step) = step.up() { if let Some(sq) = step.right() {
+            moves.0 |= Bitboard::from_square(sq).0;
+        }}}
+        if let Some(step) = square.down() { if let Some(step) = step.down() { if let Some(sq) = step.left() {
+            moves.0 |= Bitboard::from_square(sq).0;
+        }}}
+        if let Some(step) = square.down() { if let Some(step) = step.down() { if let Some(sq) = step.right() {
+            moves.0 |= Bitboard::from_square(sq).0;
+        }}}
+        if let Some(step) = square.left() { if let Some(step) = step.left() { if let Some(sq) = step.up() {
+            moves.0 |= Bitboard::from_square(sq).0;
+        }}}
+        if let Some(step) = square.left() { if let Some(step) = step.left() { if let Some(sq) = step.down() {
+            moves.0 |= Bitboard::from_square(sq).0;
+        }}}
+        if let Some(step) = square.right() { if let Some(step) = step.right() { if let Some(sq) = step.up() {
+            moves.0 |= Bitboard::from_square(sq).0;
+        }}}
+        if let Some(step) = square.right() { if let Some(step) = step.right() { if let Some(sq) = step.down() {
+            moves.0 |= Bitboard::from_square(sq).0;
+        }}}
+
+        knight_moves[square_idx] = moves;
+        square_idx += 1;
+    }
+
+    knight_moves
+};
+
+const KING_MOVES: [Bitboard; NUM_SQUARES] = {
+    let mut king_moves = [Bitboard::EMPTY; NUM_SQUARES];
+    let mut square_idx = 0;
+    while square_idx < NUM_SQUARES {
+        let square = Square::from_idx(square_idx);
+        let mut moves = Bitboard::EMPTY;
+
+        if let Some(step) = square.up() { if let Some(sq) = step.left() {
+            moves.0 |= Bitboard::from_square(sq).0;
+        }}
+        if let Some(sq) = square.up() {
+            moves.0 |= Bitboard::from_square(sq).0;
+        }
+        if let Some(step) = square.up() { if let Some(sq) = step.right() {
+            moves.0 |= Bitboard::from_square(sq).0;
+        }}
+        if let Some(sq) = square.right() {
+            moves.0 |= Bitboard::from_square(sq).0;
+        }
+        if let Some(step) = square.down() { if let Some(sq) = step.right() {
+            moves.0 |= Bitboard::from_square(sq).0;
+        }}
+        if let Some(sq) = square.down() {
+            moves.0 |= Bitboard::from_square(sq).0;
+        }
+        if let Some(step) = square.down() { if let Some(sq) = step.left() {
+            moves.0 |= Bitboard::from_square(sq).0;
+        }}
+        if let Some(sq) = square.left() {
+            moves.0 |= Bitboard::from_square(sq).0;
+        }
+
+        king_moves[square_idx] = moves;
+        square_idx += 1;
+    }
+
+    king_moves
+};
+
+pub(super) const PAWN_LEFT_CAPTURES: [[Option<Square>; NUM_SQUARES]; NUM_COLORS] = {
+    let mut captures = [[None; NUM_SQUARES]; NUM_COLORS];
+    let mut square_idx = 0;
+    while square_idx < NUM_SQUARES {
+        let square = Square::from_idx(square_idx);
+        match square.rank() {
+            Rank::One | Rank::Eight => { square_idx += 1; continue },
+            _ => ()
+        };
+
+        captures[Color::White.idx()][square_idx] = square.up().unwrap().left();
+        captures[Color::Black.idx()][square_idx] = square.down().unwrap().left();
+        square_idx += 1;
+    }
+    captures
+};
+
+pub(super) const PAWN_RIGHT_CAPTURES: [[Option<Square>; NUM_SQUARES]; NUM_COLORS] = {
+    let mut captures = [[None; NUM_SQUARES]; NUM_COLORS];
+    let mut square_idx = 0;
+    while square_idx < NUM_SQUARES {
+        let square = Square::from_idx(square_idx);
+        match square.rank() {
+            Rank::One | Rank::Eight => { square_idx += 1; continue },
+            _ => ()
+        };
+
+        captures[Color::White.idx()][square_idx] = square.up().unwrap().right();
+        captures[Color::Black.idx()][square_idx] = square.down().unwrap().right();
+        square_idx += 1;
+    }
+    captures
+};
+
+// Unlike the capture tables, this covers the first and last ranks, so it can be looked up from any square
+const PAWN_ATTACKS: [[Bitboard; NUM_SQUARES]; NUM_COLORS] = {
+    let mut attacks = [[Bitboard::EMPTY; NUM_SQUARES]; NUM_COLORS];
+    let mut square_idx = 0;
+    while square_idx < NUM_SQUARES {
+        let square = Square::from_idx(square_idx);
+
+        if let Some(fwd) = square.up() {
+            if let Some(capture) = fwd.left() { attacks[Color::White.idx()][square_idx].0 |= Bitboard::from_square(capture).0; }
+            if let Some(capture) = fwd.right() { attacks[Color::White.idx()][square_idx].0 |= Bitboard::from_square(capture).0; }
+        }
+        if let Some(fwd) = square.down() {
+            if let Some(capture) = fwd.left() { attacks[Color::Black.idx()][square_idx].0 |= Bitboard::from_square(capture).0; }
+            if let Some(capture) = fwd.right() { attacks[Color::Black.idx()][square_idx].0 |= Bitboard::from_square(capture).0; }
+        }
+        square_idx += 1;
+    }
+    attacks
+};
+// Squares strictly between two squares on the same rank, file or diagonal; empty otherwise
+pub static BETWEEN: [[Bitboard; NUM_SQUARES]; NUM_SQUARES] = {
+    let mut between = [[Bitboard::EMPTY; NUM_SQUARES]; NUM_SQUARES];
+    let mut square_idx = 0;
+    while square_idx < NUM_SQUARES {
+        let mut dir_idx = 0;
+        while dir_idx < DIRECTIONS.len() {
+            let (file_step, rank_step) = DIRECTIONS[dir_idx];
+            // Walk away from the square, remembering the squares passed on the way
+            let mut passed = Bitboard::EMPTY;
+            let mut next = step(square_idx, file_step, rank_step);
+            while let Some(other_idx) = next {
+                between[square_idx][other_idx] = passed;
+                passed.0 |= 1 << other_idx;
+                next = step(other_idx, file_step, rank_step);
+            }
+            dir_idx += 1;
+        }
+        square_idx += 1;
+    }
+    between
+};
+
+// The whole rank, file or diagonal through two squares (including them); empty if they aren't aligned
+pub static LINE: [[Bitboard; NUM_SQUARES]; NUM_SQUARES] = {
+    let mut lines = [[Bitboard::EMPTY; NUM_SQUARES]; NUM_SQUARES];
+    let mut square_idx = 0;
+    while square_idx < NUM_SQUARES {
+        // Opposite directions are on the same line, so the first half of `DIRECTIONS` covers every line
+        let mut dir_idx = 0;
+        while dir_idx < DIRECTIONS.len() / 2 {
+            let (file_step, rank_step) = DIRECTIONS[dir_idx];
+            let line = ray(square_idx, file_step, rank_step).0 | ray(square_idx, -file_step, -rank_step).0 | 1 << square_idx;
+
+            let mut squares = line & !(1 << square_idx);
+            while squares != 0 {
+                lines[square_idx][squares.trailing_zeros() as usize] = Bitboard(line);
+                squares &= squares - 1;
+            }
+            dir_idx += 1;
+        }
+        square_idx += 1;
+    }
+    lines
+};
+
+// As (file, rank) steps, with each direction's opposite 4 places after it
+const DIRECTIONS: [(isize, isize); 8] = [(0, 1), (1, 1), (1, 0), (1, -1), (0, -1), (-1, -1), (-1, 0), (-1, 1)];
+
+const fn step(square_idx: usize, file_step: isize, rank_step: isize) -> Option<usize> {
+    let file = (square_idx % NUM_FILES) as isize + file_step;
+    let rank = (square_idx / NUM_FILES) as isize + rank_step;
+    if file < 0 || file >= NUM_FILES as isize || rank < 0 || rank >= NUM_RANKS as isize {
+        None
+    } else {
+        Some(rank as usize * NUM_FILES + file as usize)
+    }
+}
+
+const fn ray(square_idx: usize, file_step: isize, rank_step: isize) -> Bitboard {
+    // Every square in a direction from a square, not including it
+    let mut ray = Bitboard::EMPTY;
+    let mut next = step(square_idx, file_step, rank_step);
+    while let Some(idx) = next {
+        ray.0 |= 1 << idx;
+        next = step(idx, file_step, rank_step);
+    }
+    ray
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bitboard(squares: &[&str]) -> Bitboard {
+        squares.iter().fold(Bitboard::EMPTY, |bb, san| bb | Bitboard::from_square(Square::from_san(san).unwrap()))
+    }
+
+    #[test]
+    fn between_and_line() {
+        let sq = |san| Square::from_san(san).unwrap().idx();
+        let diagonal = bitboard(&["a1", "b2", "c3", "d4", "e5", "f6", "g7", "h8"]);
+        let file = bitboard(&["a1", "a2", "a3", "a4", "a5", "a6", "a7", "a8"]);
+
+        // The a1-h8 diagonal, from either end and from partway along
+        assert_eq!(BETWEEN[sq("a1")][sq("h8")], bitboard(&["b2", "c3", "d4", "e5", "f6", "g7"]));
+        assert_eq!(BETWEEN[sq("h8")][sq("a1")], BETWEEN[sq("a1")][sq("h8")]);
+        assert_eq!(BETWEEN[sq("c3")][sq("f6")], bitboard(&["d4", "e5"]));
+        assert_eq!(LINE[sq("a1")][sq("h8")], diagonal);
+        assert_eq!(LINE[sq("c3")][sq("f6")], diagonal);
+
+        // The a-file
+        assert_eq!(BETWEEN[sq("a1")][sq("a8")], bitboard(&["a2", "a3", "a4", "a5", "a6", "a7"]));
+        assert_eq!(LINE[sq("a8")][sq("a1")], file);
+        assert_eq!(LINE[sq("a3")][sq("a4")], file);
+
+        // Neighbors have nothing between them but are still on a line
+        assert_eq!(BETWEEN[sq("d4")][sq("e5")], Bitboard::EMPTY);
+        assert_eq!(LINE[sq("d4")][sq("e5")], diagonal);
+
+        // Squares that aren't aligned, or the same square twice
+        for (a, b) in [("a1", "b3"), ("a1", "h7"), ("e4", "f6"), ("c2", "h8"), ("d4", "d4")] {
+            assert_eq!(BETWEEN[sq(a)][sq(b)], Bitboard::EMPTY, "{a} {b}");
+            assert_eq!(LINE[sq(a)][sq(b)], Bitboard::EMPTY, "{a} {b}");
+        }
+    }
+}
@@ -3,11 +3,14 @@ use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, N
 use super::square::Square;
 
 #[repr(transparent)]
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Bitboard(pub u64);
 
 impl Bitboard {
     pub const EMPTY: Bitboard = Bitboard(0);
+    // a1 is dark and h1 is light
+    pub const LIGHT_SQUARES: Bitboard = Bitboard(0x55AA55AA55AA55AA);
+    pub const DARK_SQUARES: Bitboard = Bitboard(0xAA55AA55AA55AA55);
 
     #[inline]
     pub const fn from_square(square: Square) -> Self {
@@ -18,6 +21,27 @@ impl Bitboard {
     pub const fn to_square(self) -> Square {
         Square::from_idx(self.0.trailing_zeros() as usize)
     }
+
+    #[inline]
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    #[inline]
+    pub const fn count(self) -> u32 {
+        self.0.count_ones()
+    }
+
+    #[inline]
+    pub const fn contains(self, square: Square) -> bool {
+        self.0 & Bitboard::from_square(square).0 != 0
+    }
+
+    #[inline]
+    pub const fn flip_vertical(self) -> Self {
+        // Rank 1 becomes rank 8 and so on
+        Self(self.0.swap_bytes())
+    }
 }
 
 impl BitAnd for Bitboard {
@@ -85,4 +109,46 @@ impl std::fmt::Display for Bitboard {
             .map(|b| format!("{:08b}", b.reverse_bits()).replace("1", "#").replace("0", "."))
             .join("\n"))
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn light_and_dark_squares() {
+        assert!((Bitboard::LIGHT_SQUARES & Bitboard::DARK_SQUARES).is_empty());
+        assert_eq!(Bitboard::LIGHT_SQUARES | Bitboard::DARK_SQUARES, Bitboard(u64::MAX));
+        assert!(!Square::from_san("a1").unwrap().is_light());
+        assert!(Square::from_san("h1").unwrap().is_light());
+
+        for square in Square::iter() {
+            assert_eq!(Bitboard::LIGHT_SQUARES.contains(square), square.is_light(), "{}", square);
+            assert_eq!(Bitboard::DARK_SQUARES.contains(square), !square.is_light(), "{}", square);
+            for neighbor in [square.up(), square.right()].into_iter().flatten() {
+                assert_ne!(square.is_light(), neighbor.is_light(), "{} {}", square, neighbor);
+            }
+        }
+    }
+
+    #[test]
+    fn bitboard_helpers() {
+        let sq = |san| Square::from_san(san).unwrap();
+        let bb = Bitboard::from_square(sq("a1")) | Bitboard::from_square(sq("e4")) | Bitboard::from_square(sq("h8"));
+
+        assert!(Bitboard::EMPTY.is_empty());
+        assert!(!bb.is_empty());
+        assert_eq!(Bitboard::EMPTY.count(), 0);
+        assert_eq!(bb.count(), 3);
+        assert_eq!(Bitboard(u64::MAX).count(), 64);
+
+        assert!(bb.contains(sq("e4")));
+        assert!(!bb.contains(sq("e5")));
+        assert!(!Bitboard::EMPTY.contains(sq("a1")));
+
+        // Files stay the same and ranks are mirrored
+        let flipped = bb.flip_vertical();
+        assert_eq!(flipped, Bitboard::from_square(sq("a8")) | Bitboard::from_square(sq("e5")) | Bitboard::from_square(sq("h1")));
+        assert_eq!(flipped.flip_vertical(), bb);
+        assert_eq!(Bitboard::LIGHT_SQUARES.flip_vertical(), Bitboard::DARK_SQUARES);
+    }
+}
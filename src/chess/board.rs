@@ -1,3 +1,4 @@
+use super::attacks::*;
 use super::bitboard::Bitboard;
 use super::color::*;
 use super::magic_tables;
@@ -5,7 +6,7 @@ use super::mv::{Move, MoveType};
 use super::piece::*;
 use super::square::*;
 
-pub const START_POS_FEN: &'static str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+pub const START_POS_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
 #[derive(Debug, Clone, Copy)]
 pub enum Castle {
@@ -15,8 +16,55 @@ pub enum Castle {
     BQ = 8
 }
 
+const CASTLES: [Castle; 4] = [Castle::WK, Castle::WQ, Castle::BK, Castle::BQ];
+
+// Where the castling rooks start in standard chess; Chess960 positions can have them elsewhere
+const STANDARD_CASTLE_ROOKS: [Square; 4] = [Square::H1, Square::A1, Square::H8, Square::A8];
+
+impl Castle {
+    #[inline]
+    pub const fn idx(self) -> usize {
+        (self as u8).trailing_zeros() as usize
+    }
+
+    #[inline]
+    pub const fn color(self) -> Color {
+        match self {
+            Castle::WK | Castle::WQ => Color::White,
+            Castle::BK | Castle::BQ => Color::Black
+        }
+    }
+
+    #[inline]
+    pub const fn is_kingside(self) -> bool {
+        matches!(self, Castle::WK | Castle::BK)
+    }
+
+    // The same castle for the other color
+    #[inline]
+    pub const fn mirror(self) -> Self {
+        match self {
+            Castle::WK => Castle::BK,
+            Castle::WQ => Castle::BQ,
+            Castle::BK => Castle::WK,
+            Castle::BQ => Castle::WQ
+        }
+    }
+
+    // Where the king and rook end up, which is the same in standard chess and Chess960
+    #[inline]
+    const fn destinations(self) -> (Square, Square) {
+        match self {
+            Castle::WK => (Square::G1, Square::F1),
+            Castle::WQ => (Square::C1, Square::D1),
+            Castle::BK => (Square::G8, Square::F8),
+            Castle::BQ => (Square::C8, Square::D8)
+        }
+    }
+}
+
 #[repr(transparent)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Castles(u8);
 
 impl Castles {
@@ -49,45 +97,66 @@ impl Castles {
     }
 }
 
-pub const CASTLE_WK_MOVE: Move = Move {
-    from: Square::E1,
-    to: Square::G1,
-    move_type: MoveType::Castle
-};
-pub const CASTLE_WQ_MOVE: Move = Move {
-    from: Square::E1,
-    to: Square::C1,
-    move_type: MoveType::Castle
-};
-pub const CASTLE_BK_MOVE: Move = Move {
-    from: Square::E8,
-    to: Square::G8,
-    move_type: MoveType::Castle
-};
-pub const CASTLE_BQ_MOVE: Move = Move {
-    from: Square::E8,
-    to: Square::C8,
-    move_type: MoveType::Castle
-};
-
-// #[derive(Debug, Clone, Copy, PartialEq)]
-// pub enum BoardState {
-//     Live,
-//     WhiteWin,
-//     BlackWin,
-//     Stalemate,
-//     ThreefoldRepetition,
-//     FiftyMoveRule,
-//     InsufficientMaterial
-// }
-
-// struct MoveUndoer {
-//     mv: Move,
-//     captured: Option<(Piece, Color)>,
-//     en_passant: Option<Square>,
-//     castling: Castles,
-//     halfmoves: u32
-// }
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BoardState {
+    Live,
+    WhiteWin,
+    BlackWin,
+    Stalemate,
+    ThreefoldRepetition,
+    FiftyMoveRule,
+    InsufficientMaterial
+}
+
+// Everything `make_move_in_place` can't recover from the move itself
+#[derive(Debug, Clone, Copy)]
+pub struct MoveUndoer {
+    mv: Move,
+    captured: Option<Piece>,
+    en_passant: Option<Square>,
+    castles: Castles,
+    halfmoves: u8,
+    hash: u64
+}
+
+impl MoveUndoer {
+    pub const fn captured(&self) -> Option<Piece> {
+        // The piece the move took, including the pawn taken en passant
+        match self.mv.move_type {
+            MoveType::EnPassant => Some(Piece::Pawn),
+            _ => self.captured
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FenError {
+    NotAscii,
+    WrongFieldCount,
+    BadRank,
+    BadSideToMove,
+    BadCastling,
+    BadEnPassant,
+    BadHalfmove,
+    BadFullmove
+}
+
+impl std::fmt::Display for FenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            FenError::NotAscii => "FEN contains non-ASCII characters",
+            FenError::WrongFieldCount => "FEN must have four or six space-separated fields",
+            FenError::BadRank => "FEN piece placement must have eight ranks of eight squares each",
+            FenError::BadSideToMove => "FEN side to move must be 'w' or 'b'",
+            FenError::BadCastling => "FEN castling availability must be '-' or some of 'KQkq'",
+            FenError::BadEnPassant => "FEN en passant target must be '-' or a square",
+            FenError::BadHalfmove => "FEN halfmove clock must be a number from 0 to 255",
+            FenError::BadFullmove => "FEN fullmove number must be a non-negative number",
+        })
+    }
+}
+
+impl std::error::Error for FenError {}
 
 #[derive(Clone, Copy)]
 pub struct Board {
@@ -97,32 +166,52 @@ pub struct Board {
     castles: Castles,
     en_passant: Option<Square>,
     halfmoves: u8,
+    fullmove: u32,
+    // Half-moves played since the start of the game, or since the fullmove number and side to move of a FEN
+    ply: u32,
+    // Starting square of the rook for each castle, indexed by `Castle::idx`
+    castle_rooks: [Square; 4],
+    // Zobrist hash, updated incrementally as moves are made
+    hash: u64,
 }
 
 impl Board {
+    #[inline]
     pub fn new(fen: &str) -> Option<Self> {
-        if !fen.is_ascii() || fen.is_empty() { return None; }
+        Self::try_from_fen(fen).ok()
+    }
+
+    pub fn try_from_fen(fen: &str) -> Result<Self, FenError> {
+        if !fen.is_ascii() { return Err(FenError::NotAscii); }
 
+        // The move counters are often left out, in which case they're those of a new game
+        let mut fields = fen.trim().split(" ").collect::<Vec<_>>();
+        if fields.len() == 4 {
+            fields.extend(["0", "1"]);
+        }
         let [
             board, side_to_move, allowed_castling, en_passant, halfmove_count, fullmove_num
-        ] = fen.trim().split(" ").collect::<Vec<_>>().try_into().ok()?;
+        ] = fields.try_into().map_err(|_| FenError::WrongFieldCount)?;
 
         // Board
         let mut pieces = [Bitboard::EMPTY; NUM_PIECES];
         let mut colors = [Bitboard::EMPTY; NUM_COLORS];
 
-        // TODO: check for repeated numbers (e.g. "44") in fen
         let mut rank = b'8';
         for row in board.split("/") {
-            if rank < b'1' { return None; }
+            if rank < b'1' { return Err(FenError::BadRank); }
 
             let mut file = b'a';
+            let mut prev_was_digit = false;
             for char in row.bytes() {
-                if file > b'h' { return None; }
+                if file > b'h' { return Err(FenError::BadRank); }
 
                 // Check if character is a number
-                if char >= b'1' && char <= b'8' {
+                // Runs of empty squares must be a single digit (not e.g. "44") that fits in the rest of the rank
+                if (b'1'..=b'8').contains(&char) {
+                    if prev_was_digit || file + (char - b'0') > b'i' { return Err(FenError::BadRank); }
                     file += char - b'0';
+                    prev_was_digit = true;
                 }
                 else if let Some(piece) = Piece::from_ascii(char) {
                     let color = if char.is_ascii_uppercase() { Color::White } else { Color::Black };
@@ -131,47 +220,232 @@ impl Board {
                     pieces[piece.idx()] ^= bb;
                     colors[color.idx()] ^= bb;
                     file += 1;
+                    prev_was_digit = false;
                 }
                 else {
-                    return None;
+                    return Err(FenError::BadRank);
                 }
             }
-            if file != b'i' { return None; }
+            if file != b'i' { return Err(FenError::BadRank); }
             rank -= 1;
         }
-        if rank != b'0' { return None; }
+        if rank != b'0' { return Err(FenError::BadRank); }
 
         // Side to move
         let side_to_move = match side_to_move {
             "w" => Color::White,
             "b" => Color::Black,
-            _ => return None
+            _ => return Err(FenError::BadSideToMove)
         };
 
         // Castling avilability
+        // KQkq refer to the outermost rook on that side of the king; Shredder-FEN file letters (for Chess960) name the rook's file
+        if allowed_castling.is_empty() { return Err(FenError::BadCastling); }
         let mut castles = Castles::NONE;
-        if allowed_castling.contains("K") { castles.set(Castle::WK); }
-        if allowed_castling.contains("Q") { castles.set(Castle::WQ); }
-        if allowed_castling.contains("k") { castles.set(Castle::BK); }
-        if allowed_castling.contains("q") { castles.set(Castle::BQ); }
+        let mut castle_rooks = STANDARD_CASTLE_ROOKS;
+        if allowed_castling != "-" {
+            for char in allowed_castling.bytes() {
+                let color = if char.is_ascii_uppercase() { Color::White } else { Color::Black };
+                let rank = back_rank(color);
+                let rank_bb = Bitboard(0xFF << (8 * rank as u8));
+                let king = (pieces[Piece::King.idx()] & colors[color.idx()] & rank_bb).next();
+                let mut rooks = pieces[Piece::Rook.idx()] & colors[color.idx()] & rank_bb;
+
+                let (kingside, queenside) = match color {
+                    Color::White => (Castle::WK, Castle::WQ),
+                    Color::Black => (Castle::BK, Castle::BQ)
+                };
+
+                // Without a matching king and rook, KQkq keep the standard rook squares
+                let (castle, rook) = match char.to_ascii_uppercase() {
+                    b'K' => (kingside, king.and_then(|king| rooks.filter(|sq| sq.idx() > king.idx()).last())),
+                    b'Q' => (queenside, king.and_then(|king| rooks.find(|sq| sq.idx() < king.idx()))),
+                    b'A'..=b'H' => {
+                        let Some(king) = king else { return Err(FenError::BadCastling); };
+                        let rook = Square::from_coords(File::from_ascii(char.to_ascii_lowercase()), rank);
+                        (if rook.idx() > king.idx() { kingside } else { queenside }, Some(rook))
+                    },
+                    _ => return Err(FenError::BadCastling)
+                };
+
+                castles.set(castle);
+                if let Some(rook) = rook {
+                    castle_rooks[castle.idx()] = rook;
+                }
+            }
+        }
 
         // En passant
         let en_passant = match en_passant {
             "-" => None,
-            san => Some(Square::from_san(san)?)
+            san => Some(Square::from_san(san).ok_or(FenError::BadEnPassant)?)
         };
 
         // Halfmove count
-        let Ok(halfmoves) = halfmove_count.parse::<u8>() else { return None; };
+        let halfmoves = halfmove_count.parse::<u8>().map_err(|_| FenError::BadHalfmove)?;
         // Fullmove num
-        let Ok(_) = fullmove_num.parse::<u32>() else { return None; };
+        let fullmove = fullmove_num.parse::<u32>().map_err(|_| FenError::BadFullmove)?;
+
+        let ply = starting_ply(fullmove, side_to_move);
+        Ok(Self { pieces, colors, side_to_move, castles, en_passant, halfmoves, fullmove, ply, castle_rooks, hash: 0 }.with_hash())
+    }
+
+    pub fn from_parts(placement: &[(Square, Color, Piece)], side_to_move: Color, castles: Castles, en_passant: Option<Square>) -> Option<Self> {
+        // Builds a position at move 1 with standard castling rooks
+        // Fails on a doubly-occupied square, anything but one king per side, or pawns on the back ranks
+        let mut pieces = [Bitboard::EMPTY; NUM_PIECES];
+        let mut colors = [Bitboard::EMPTY; NUM_COLORS];
+
+        for &(square, color, piece) in placement {
+            if (colors[0] | colors[1]).contains(square) { return None; }
+            if piece == Piece::Pawn && matches!(square.rank(), Rank::One | Rank::Eight) { return None; }
+
+            let bb = Bitboard::from_square(square);
+            pieces[piece.idx()] |= bb;
+            colors[color.idx()] |= bb;
+        }
+
+        for color in COLORS {
+            if (pieces[Piece::King.idx()] & colors[color.idx()]).count() != 1 { return None; }
+        }
 
-        Some(Self { pieces, colors, side_to_move, castles, en_passant, halfmoves })
+        Some(Self {
+            pieces,
+            colors,
+            side_to_move,
+            castles,
+            en_passant,
+            halfmoves: 0,
+            fullmove: 1,
+            ply: starting_ply(1, side_to_move),
+            castle_rooks: STANDARD_CASTLE_ROOKS,
+            hash: 0
+        }.with_hash())
     }
 
+    // Fills in the hash from scratch, for newly built boards
     #[inline]
-    pub fn default() -> Self {
-        Self::new(START_POS_FEN).unwrap()
+    fn with_hash(mut self) -> Self {
+        self.hash = crate::ZOBRIST_HASHER.hash(&self);
+        self
+    }
+
+    pub fn get_fen(&self) -> String {
+        let mut fen = String::new();
+
+        // Board
+        for rank in RANKS.into_iter().rev() {
+            let mut empty = 0;
+            for file in FILES {
+                let square = Square::from_coords(file, rank);
+                if let Some(color) = self.get_color_at(square) {
+                    if empty > 0 {
+                        fen += &empty.to_string();
+                        empty = 0;
+                    }
+                    let piece = self.get_piece_at(square).unwrap().to_string();
+                    fen += &match color {
+                        Color::White => piece.to_ascii_uppercase(),
+                        Color::Black => piece
+                    };
+                } else {
+                    empty += 1;
+                }
+            }
+            if empty > 0 {
+                fen += &empty.to_string();
+            }
+            if rank != Rank::One {
+                fen.push('/');
+            }
+        }
+
+        // Side to move
+        fen += match self.side_to_move {
+            Color::White => " w ",
+            Color::Black => " b "
+        };
+
+        // Castling avilability
+        // Rooks that don't start in the corners are written by file, as in Shredder-FEN
+        let len = fen.len();
+        for castle in CASTLES.into_iter().filter(|&castle| self.castles.is_set(castle)) {
+            let rook = self.castle_rooks[castle.idx()];
+            let char = if rook == STANDARD_CASTLE_ROOKS[castle.idx()] {
+                if castle.is_kingside() { 'k' } else { 'q' }
+            } else {
+                (rook.file() as u8 + b'a') as char
+            };
+            fen.push(match castle.color() {
+                Color::White => char.to_ascii_uppercase(),
+                Color::Black => char
+            });
+        }
+        if fen.len() == len { fen.push('-'); }
+
+        // En passant: the square behind the pawn that just moved two, as `try_from_fen` reads it. It's only written
+        // when a pawn can capture onto it, so after 1. e4 from the start position the field is `-`, but `e3` if a
+        // black pawn stands on d4 or f4
+        match self.effective_en_passant() {
+            Some(square) => fen += &format!(" {}", square),
+            None => fen += " -"
+        }
+
+        // Move counts
+        fen += &format!(" {} {}", self.halfmoves, self.fullmove);
+
+        fen
+    }
+
+    pub fn from_pgn(pgn: &str) -> Option<(Self, Vec<Move>)> {
+        // Tag pairs: only the starting position matters here
+        let mut board = Self::default();
+        for line in pgn.lines().map(str::trim).filter(|line| line.starts_with('[')) {
+            if let Some(fen) = line.strip_prefix("[FEN \"").and_then(|rest| rest.strip_suffix("\"]")) {
+                board = Self::new(fen)?;
+            }
+        }
+
+        // Strip comments, variations, and tag pairs from the movetext
+        let mut movetext = String::new();
+        let mut chars = pgn.chars();
+        let mut variation_depth = 0;
+        while let Some(c) = chars.next() {
+            match c {
+                '{' => { chars.by_ref().find(|&c| c == '}')?; },
+                ';' => { chars.by_ref().find(|&c| c == '\n'); },
+                '[' if variation_depth == 0 => { chars.by_ref().find(|&c| c == ']')?; },
+                '(' => variation_depth += 1,
+                ')' => {
+                    if variation_depth == 0 { return None; }
+                    variation_depth -= 1;
+                },
+                c if variation_depth == 0 => movetext.push(c),
+                _ => {}
+            }
+        }
+        if variation_depth != 0 { return None; }
+
+        let mut moves = Vec::new();
+        for token in movetext.split_whitespace() {
+            // Result token ends the game
+            if matches!(token, "1-0" | "0-1" | "1/2-1/2" | "*") { break; }
+            // NAGs
+            if token.starts_with('$') { continue; }
+
+            // Move numbers, possibly attached to the move (e.g. "1.e4" or "3...Nf6")
+            let san = match token.find('.') {
+                Some(idx) if token[..idx].bytes().all(|b| b.is_ascii_digit()) => token[idx..].trim_start_matches('.'),
+                _ => token
+            };
+            if san.is_empty() { continue; }
+
+            let mv = Move::from_san(san, &board)?;
+            board = make_move(&board, mv);
+            moves.push(mv);
+        }
+
+        Some((board, moves))
     }
 
     #[inline]
@@ -184,6 +458,16 @@ impl Board {
         self.colors[color.idx()]
     }
 
+    #[inline]
+    pub const fn pieces_of(&self, color: Color, piece: Piece) -> Bitboard {
+        Bitboard(self.pieces[piece.idx()].0 & self.colors[color.idx()].0)
+    }
+
+    pub fn piece_list(&self, color: Color) -> impl Iterator<Item = (Square, Piece)> + '_ {
+        // Every piece of `color` with its square, grouped by piece type in `PIECES` order
+        PIECES.into_iter().flat_map(move |piece| self.pieces_of(color, piece).map(move |square| (square, piece)))
+    }
+
     #[inline]
     pub const fn get_side_to_move(&self) -> Color {
         self.side_to_move
@@ -194,6 +478,17 @@ impl Board {
         self.castles
     }
 
+    // Starting square of the rook for each castle that's still allowed, indexed by `Castle::idx`
+    #[inline]
+    pub fn get_castle_rooks(&self) -> [Option<Square>; 4] {
+        CASTLES.map(|castle| self.castles.is_set(castle).then_some(self.castle_rooks[castle.idx()]))
+    }
+
+    #[inline]
+    pub const fn get_hash(&self) -> u64 {
+        self.hash
+    }
+
     pub fn get_piece_at(&self, square: Square) -> Option<Piece> {
         let square = Bitboard::from_square(square);
 
@@ -215,7 +510,7 @@ impl Board {
             }
             return Some(Piece::Pawn);
         }
-        return None;
+        None
         // for (piece, bitboard) in PIECES.into_iter().zip(&self.pieces) {
         //     if *bitboard & square != Bitboard::EMPTY {
         //         return Some(piece);
@@ -226,59 +521,325 @@ impl Board {
 
     pub fn get_color_at(&self, square: Square) -> Option<Color> {
         let square = Bitboard::from_square(square);
-        for color in COLORS {
-            if self.colors[color.idx()] & square != Bitboard::EMPTY {
-                return Some(color);
-            }
-        }
-        None
+        COLORS.into_iter().find(|&color| self.colors[color.idx()] & square != Bitboard::EMPTY)
     }
 
     #[inline(always)]
     pub const fn get_en_passant(&self) -> Option<Square> { self.en_passant }
 
+    pub fn effective_en_passant(&self) -> Option<Square> {
+        // The en passant square, but only if a pawn of the side to move is there to capture onto it.
+        // FENs and hashes use this, so positions that only differ by an unusable en passant square are the same.
+        self.en_passant.filter(|&square| self.can_capture_en_passant(square, self.side_to_move))
+    }
+
+    #[inline]
+    fn can_capture_en_passant(&self, square: Square, by: Color) -> bool {
+        // `by`'s pawns that attack `square` are on the squares an enemy pawn there would attack
+        let pawns = self.pieces[Piece::Pawn.idx()] & self.colors[by.idx()];
+        !(pawn_attacks(square, !by) & pawns).is_empty()
+    }
+
+    #[inline(always)]
+    pub const fn get_halfmoves(&self) -> u8 { self.halfmoves }
+
+    #[inline(always)]
+    pub const fn get_fullmove(&self) -> u32 { self.fullmove }
+
+    #[inline(always)]
+    pub const fn ply(&self) -> u32 {
+        // Half-moves played since the start of the game: 0 at the start position, 1 after White's first move.
+        // Positions set up from a FEN start from what the fullmove number and side to move imply
+        self.ply
+    }
+
     #[inline(always)]
     pub fn blockers(&self) -> Bitboard {
         self.colors[Color::White.idx()] | self.colors[Color::Black.idx()]
     }
 
+    pub fn mirror(&self) -> Self {
+        // Flips the board vertically and swaps the colors, giving the same position from the other side's perspective
+        let flip = |square: Square| Square::from_idx(square.idx() ^ 56);
+
+        let mut castles = Castles::NONE;
+        for castle in CASTLES {
+            if self.castles.is_set(castle) {
+                castles.set(castle.mirror());
+            }
+        }
+
+        Self {
+            pieces: self.pieces.map(Bitboard::flip_vertical),
+            colors: [self.colors[Color::Black.idx()].flip_vertical(), self.colors[Color::White.idx()].flip_vertical()],
+            side_to_move: !self.side_to_move,
+            castles,
+            en_passant: self.en_passant.map(flip),
+            halfmoves: self.halfmoves,
+            fullmove: self.fullmove,
+            ply: self.ply,
+            castle_rooks: CASTLES.map(|castle| flip(self.castle_rooks[castle.mirror().idx()])),
+            hash: 0
+        }.with_hash()
+    }
+
     #[inline]
     pub fn is_check(&self) -> bool {
-        self.pieces[Piece::King.idx()] & self.colors[(!self.side_to_move).idx()]
-        & gen_attacks(self, self.side_to_move, self.blockers()) != Bitboard::EMPTY
+        !self.checkers().is_empty()
+    }
+
+    pub fn is_checkmate(&self) -> bool {
+        self.is_check() && self.has_no_legal_moves()
+    }
+
+    pub fn is_stalemate(&self) -> bool {
+        !self.is_check() && self.has_no_legal_moves()
+    }
+
+    fn has_no_legal_moves(&self) -> bool {
+        let mut moves = Vec::new();
+        gen_legal_moves(self, &mut moves);
+        moves.is_empty()
+    }
+
+    pub fn legal_move_from_uci(&self, uci: &str) -> Option<Move> {
+        // The move `uci` describes, if it's well-formed and legal here. Only the moving piece's moves are generated
+        let mv = Move::from_uci(uci, self)?;
+        // Only promotions have a fifth character
+        if uci.len() != if mv.is_promotion().is_some() { 5 } else { 4 } { return None; }
+
+        let mut moves = Vec::new();
+        legal_moves_from(self, mv.from, &mut moves);
+        moves.contains(&mv).then_some(mv)
+    }
+
+    pub fn is_legal_uci(&self, uci: &str) -> bool {
+        self.legal_move_from_uci(uci).is_some()
+    }
+
+    pub fn apply_uci_moves(&mut self, moves: &[&str]) -> Result<(), usize> {
+        // Play a list of UCI moves, as in `position startpos moves e2e4 e7e5`, stopping at the first one that's
+        // malformed or illegal and returning its index. The moves before it stay played
+        self.apply_uci_moves_with_history(moves, &mut Vec::new())
+    }
+
+    pub fn apply_uci_moves_with_history(&mut self, moves: &[&str], history: &mut Vec<u64>) -> Result<(), usize> {
+        // As `apply_uci_moves`, also pushing the hash of each position a move is played from onto `history`
+        for (i, uci) in moves.iter().enumerate() {
+            let mv = self.legal_move_from_uci(uci).ok_or(i)?;
+            history.push(self.hash);
+            self.make_move_in_place(mv);
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn is_attacked(&self, square: Square, by: Color) -> bool {
+        !attackers_to(self, square, by, self.blockers()).is_empty()
+    }
+
+    pub fn checkers(&self) -> Bitboard {
+        // Enemy pieces attacking the side to move's king
+        match (self.pieces[Piece::King.idx()] & self.colors[self.side_to_move.idx()]).next() {
+            Some(king) => attackers_to(self, king, !self.side_to_move, self.blockers()),
+            None => Bitboard::EMPTY
+        }
+    }
+
+    pub fn repetition_count(&self, history: &[u64]) -> usize {
+        // How many times this position has occurred, counting this one; `history` is as in `state`
+        // Positions can only repeat since the last irreversible move, and only with the same side to move
+        1 + history.iter().rev()
+            .take(self.halfmoves as usize)
+            .skip(1)
+            .step_by(2)
+            .filter(|&&prev| prev == self.hash)
+            .count()
+    }
+
+    pub fn state(&self, history: &[u64]) -> BoardState {
+        // `history` holds the hashes of the positions before this one, oldest first
+        let mut moves = Vec::new();
+        gen_legal_moves(self, &mut moves);
+        if moves.is_empty() {
+            return if !self.is_check() {
+                BoardState::Stalemate
+            } else {
+                match self.side_to_move {
+                    Color::White => BoardState::BlackWin,
+                    Color::Black => BoardState::WhiteWin
+                }
+            };
+        }
+
+        if self.halfmoves >= 100 {
+            return BoardState::FiftyMoveRule;
+        }
+
+        if self.repetition_count(history) >= 3 {
+            return BoardState::ThreefoldRepetition;
+        }
+
+        if self.is_insufficient_material() {
+            return BoardState::InsufficientMaterial;
+        }
+
+        BoardState::Live
+    }
+
+    fn is_insufficient_material(&self) -> bool {
+        // Dead positions (FIDE Laws of Chess, article 5.2.2) that can be told from the material alone, where no
+        // sequence of legal moves, however bad, ends in checkmate: K vs K, K+N vs K, and kings with any number of
+        // bishops that are all on the same color of square (e.g. K+B vs K, or K+B vs K+B with same-colored bishops).
+        // Not detected: dead positions that depend on the pawn structure, like fully blocked pawn chains.
+        // Not dead, since the weaker side can blunder into mate: K+N vs K+N, K+N+N vs K, K+B vs K+N and
+        // K+B vs K+B with opposite-colored bishops.
+        let heavy = self.pieces[Piece::Queen.idx()] | self.pieces[Piece::Rook.idx()] | self.pieces[Piece::Pawn.idx()];
+        if !heavy.is_empty() {
+            return false;
+        }
+
+        let knights = self.pieces[Piece::Knight.idx()];
+        let bishops = self.pieces[Piece::Bishop.idx()];
+        match (knights.count(), bishops.count()) {
+            (0, 0) | (1, 0) => true,
+            // Bishops can never attack the other color's squares, so a king standing on one can't be mated
+            (0, _) => bishops & Bitboard::DARK_SQUARES == bishops || (bishops & Bitboard::DARK_SQUARES).is_empty(),
+            _ => false
+        }
     }
 }
 
-impl std::fmt::Display for Board {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        const fn write_piece(color: Color, piece: Piece) -> char {
-            match color {
-                Color::White => match piece {
-                    Piece::Rook => 'R',
-                    Piece::Knight => 'N',
-                    Piece::Bishop => 'B',
-                    Piece::Queen => 'Q',
-                    Piece::King => 'K',
-                    Piece::Pawn => 'P'
-                },
-                Color::Black => match piece {
-                    Piece::Rook => 'r',
-                    Piece::Knight => 'n',
-                    Piece::Bishop => 'b',
-                    Piece::Queen => 'q',
-                    Piece::King => 'k',
-                    Piece::Pawn => 'p'
-                },
+impl Default for Board {
+    #[inline]
+    fn default() -> Self {
+        Self::new(START_POS_FEN).unwrap()
+    }
+}
+
+// Boards are equal if they have the same pieces, side to move, castling rights, en passant square and halfmove clock.
+// The fullmove number isn't compared, and an en passant square the side to move can't capture onto is ignored, as in the FEN
+impl PartialEq for Board {
+    fn eq(&self, other: &Self) -> bool {
+        self.pieces == other.pieces
+            && self.colors == other.colors
+            && self.side_to_move == other.side_to_move
+            && self.castles == other.castles
+            && self.get_castle_rooks() == other.get_castle_rooks()
+            && self.effective_en_passant() == other.effective_en_passant()
+            && self.halfmoves == other.halfmoves
+    }
+}
+
+impl Eq for Board {}
+
+impl std::hash::Hash for Board {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.pieces.hash(state);
+        self.colors.hash(state);
+        self.side_to_move.hash(state);
+        self.castles.hash(state);
+        self.get_castle_rooks().hash(state);
+        self.effective_en_passant().hash(state);
+        self.halfmoves.hash(state);
+    }
+}
+
+const fn starting_ply(fullmove: u32, side_to_move: Color) -> u32 {
+    // The ply a position with this fullmove number and side to move would be reached at from move 1
+    2 * fullmove.saturating_sub(1) + match side_to_move {
+        Color::White => 0,
+        Color::Black => 1
+    }
+}
+
+const fn ascii_piece(color: Color, piece: Piece) -> char {
+    match color {
+        Color::White => match piece {
+            Piece::Rook => 'R',
+            Piece::Knight => 'N',
+            Piece::Bishop => 'B',
+            Piece::Queen => 'Q',
+            Piece::King => 'K',
+            Piece::Pawn => 'P'
+        },
+        Color::Black => match piece {
+            Piece::Rook => 'r',
+            Piece::Knight => 'n',
+            Piece::Bishop => 'b',
+            Piece::Queen => 'q',
+            Piece::King => 'k',
+            Piece::Pawn => 'p'
+        },
+    }
+}
+
+const fn unicode_piece(color: Color, piece: Piece) -> char {
+    match color {
+        Color::White => match piece {
+            Piece::Rook => '♖',
+            Piece::Knight => '♘',
+            Piece::Bishop => '♗',
+            Piece::Queen => '♕',
+            Piece::King => '♔',
+            Piece::Pawn => '♙'
+        },
+        Color::Black => match piece {
+            Piece::Rook => '♜',
+            Piece::Knight => '♞',
+            Piece::Bishop => '♝',
+            Piece::Queen => '♛',
+            Piece::King => '♚',
+            Piece::Pawn => '♟'
+        },
+    }
+}
+
+impl Board {
+    pub fn to_ascii(&self) -> String {
+        // Like `Display`, but with rank and file labels and the side to move, for printing in a terminal
+        self.labeled_diagram(ascii_piece)
+    }
+
+    pub fn to_unicode(&self) -> String {
+        // `to_ascii` with chess symbols for the pieces
+        self.labeled_diagram(unicode_piece)
+    }
+
+    fn labeled_diagram(&self, piece_char: fn(Color, Piece) -> char) -> String {
+        let mut s = String::new();
+        for rank in RANKS.into_iter().rev() {
+            s += &format!("{} ", rank as u8 + 1);
+            for file in FILES {
+                let square = Square::from_coords(file, rank);
+                if let Some(color) = self.get_color_at(square) {
+                    let piece = self.get_piece_at(square).unwrap();
+                    s.push(piece_char(color, piece));
+                    s.push(' ');
+                } else {
+                    s += ". ";
+                }
             }
+            s.push('\n');
         }
+        s += "  a b c d e f g h\n";
+        s += match self.side_to_move {
+            Color::White => "Side to move: White",
+            Color::Black => "Side to move: Black"
+        };
+        s
+    }
+}
 
+impl std::fmt::Display for Board {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut s = String::new();
         for rank in RANKS.into_iter().rev() {
             for file in FILES {
                 let square = Square::from_coords(file, rank);
                 if let Some(color) = self.get_color_at(square) {
                     let piece = self.get_piece_at(square).unwrap();
-                    s.push(write_piece(color, piece));
+                    s.push(ascii_piece(color, piece));
                     s.push(' ');
                 } else {
                     s += ". ";
@@ -290,9 +851,25 @@ impl std::fmt::Display for Board {
     }
 }
 
+// Boards are (de)serialized as FEN strings
+#[cfg(feature = "serde")]
+impl serde::Serialize for Board {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.get_fen())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Board {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let fen = String::deserialize(deserializer)?;
+        Self::try_from_fen(&fen).map_err(serde::de::Error::custom)
+    }
+}
+
 impl std::fmt::Debug for Board {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "rooks:{}\nknights:{}\nbishops:{}\nqueens:{}\nkings:{}\npawns:{}\nwhite:{}\nblack:{}\nside_to_move:{:?}\ncastles:{}{}{}{}\nen_passant:{:?}\nhalfmoves:{}",
+        write!(f, "rooks:{}\nknights:{}\nbishops:{}\nqueens:{}\nkings:{}\npawns:{}\nwhite:{}\nblack:{}\nside_to_move:{:?}\ncastles:{}{}{}{}\nen_passant:{:?}\nhalfmoves:{}\nfullmove:{}\nply:{}",
         self.pieces[Piece::Rook.idx()], self.pieces[Piece::Knight.idx()], self.pieces[Piece::Bishop.idx()], self.pieces[Piece::Queen.idx()], self.pieces[Piece::King.idx()], self.pieces[Piece::Pawn.idx()],
         self.colors[Color::White.idx()], self.colors[Color::Black.idx()],
         self.side_to_move,
@@ -300,217 +877,515 @@ impl std::fmt::Debug for Board {
         if self.castles.is_set(Castle::WQ) {"Q"} else {""},
         if self.castles.is_set(Castle::BK) {"k"} else {""},
         if self.castles.is_set(Castle::BQ) {"q"} else {""},
-        self.en_passant, self.halfmoves)
+        self.en_passant, self.halfmoves, self.fullmove, self.ply)
     }
 }
 
 pub fn make_move(board: &Board, mv: Move) -> Board {
+    let mut board = *board;
+    board.make_move_in_place(mv);
+    board
+}
+
+pub fn apply_uci_moves(board: &Board, moves: &[&str]) -> Result<Board, usize> {
+    // As `Board::apply_uci_moves`, returning the resulting board rather than changing `board`
+    let mut board = *board;
+    board.apply_uci_moves(moves)?;
+    Ok(board)
+}
+
+pub fn make_move_capturing(board: &Board, mv: Move) -> (Board, Option<Piece>) {
+    // As `make_move`, also returning the piece the move took
+    let mut board = *board;
+    let undo = board.make_move_in_place(mv);
+    (board, undo.captured())
+}
+
+impl Board {
+    // `bitboard` must be a single square
     #[inline(always)]
-    fn xor(pieces: &mut [Bitboard; 6], colors: &mut [Bitboard; 2], bitboard: Bitboard, piece: Piece, color: Color) {
-        pieces[piece.idx()] ^= bitboard;
-        colors[color.idx()] ^= bitboard;
+    fn xor(&mut self, bitboard: Bitboard, piece: Piece, color: Color) {
+        self.pieces[piece.idx()] ^= bitboard;
+        self.colors[color.idx()] ^= bitboard;
+        self.hash ^= crate::ZOBRIST_HASHER.piece_key(piece, color, bitboard.to_square());
     }
 
-    // Only legal moves should make it to this function
-    let from_bb = Bitboard::from_square(mv.from);
-    let to_bb = Bitboard::from_square(mv.to);
+    // Starting square of the rook that castles with the king landing on `king_to`, if that's a castling square
+    #[inline]
+    pub(crate) fn castle_rook(&self, king_to: Square) -> Option<Square> {
+        let castle = match (king_to.rank(), king_to.file()) {
+            (Rank::One, File::G) => Castle::WK,
+            (Rank::One, File::C) => Castle::WQ,
+            (Rank::Eight, File::G) => Castle::BK,
+            (Rank::Eight, File::C) => Castle::BQ,
+            _ => return None
+        };
+        Some(self.castle_rooks[castle.idx()])
+    }
 
-    let piece = board.get_piece_at(mv.from).unwrap();
-    let captured = board.get_piece_at(mv.to);
+    // Squares of the rook's castling move as (from, to)
+    #[inline]
+    fn castle_rook_squares(&self, mv: Move, color: Color) -> (Square, Square) {
+        let castle = match (color, mv.to.file()) {
+            (Color::White, File::G) => Castle::WK,
+            (Color::White, File::C) => Castle::WQ,
+            (Color::Black, File::G) => Castle::BK,
+            (Color::Black, File::C) => Castle::BQ,
+            _ => unreachable!()
+        };
+        (self.castle_rooks[castle.idx()], castle.destinations().1)
+    }
 
-    // Make the swap
-    let mut pieces = board.pieces;
-    let mut colors = board.colors;
+    pub fn make_move_in_place(&mut self, mv: Move) -> MoveUndoer {
+        // Only legal moves should make it to this function
+        let from_bb = Bitboard::from_square(mv.from);
+        let to_bb = Bitboard::from_square(mv.to);
 
-    let end_piece = match mv.move_type {
-        MoveType::Promotion(to) => to,
-        _ => piece
-    };
+        let piece = self.get_piece_at(mv.from).unwrap();
+        // In Chess960 the king can castle onto its own rook's square
+        let captured = match mv.move_type {
+            MoveType::Castle(_) => None,
+            _ => self.get_piece_at(mv.to)
+        };
 
-    xor(&mut pieces, &mut colors, from_bb, piece, board.side_to_move);
-    xor(&mut pieces, &mut colors, to_bb, end_piece, board.side_to_move);
-    if let Some(captured) = captured {
-        xor(&mut pieces, &mut colors, to_bb, captured, !board.side_to_move);
-    }
+        let old_en_passant = self.effective_en_passant();
 
-    // Castling move
-    if mv.move_type == MoveType::Castle {
-        let [from_file, to_file] = match mv.to.file() {
-            File::C => [File::A, File::D],
-            File::G => [File::H, File::F],
-            _ => unreachable!()
+        let undo = MoveUndoer {
+            mv,
+            captured,
+            en_passant: self.en_passant,
+            castles: self.castles,
+            halfmoves: self.halfmoves,
+            hash: self.hash
+        };
+
+        // Make the swap
+        let end_piece = match mv.move_type {
+            MoveType::Promotion(to) => to,
+            _ => piece
+        };
+
+        self.xor(from_bb, piece, self.side_to_move);
+        self.xor(to_bb, end_piece, self.side_to_move);
+        if let Some(captured) = captured {
+            self.xor(to_bb, captured, !self.side_to_move);
+        }
+
+        // Castling move
+        if mv.is_castle() {
+            let (rook_from, rook_to) = self.castle_rook_squares(mv, self.side_to_move);
+            self.xor(Bitboard::from_square(rook_from), Piece::Rook, self.side_to_move);
+            self.xor(Bitboard::from_square(rook_to), Piece::Rook, self.side_to_move);
+        }
+
+        // En passant capture
+        if mv.move_type == MoveType::EnPassant {
+            self.xor(Bitboard::from_square(
+                Square::from_coords(mv.to.file(), mv.from.rank())
+            ), Piece::Pawn, !self.side_to_move);
+        }
+
+        // Update castles: moving the king loses both castles, and moving or capturing a rook loses its castle
+        let old_castles = self.castles;
+        let move_bb = from_bb | to_bb;
+        for castle in CASTLES {
+            if piece == Piece::King && castle.color() == self.side_to_move
+            || move_bb.contains(self.castle_rooks[castle.idx()]) {
+                self.castles.unset(castle);
+            }
+        }
+        if self.castles != old_castles {
+            self.hash ^= crate::ZOBRIST_HASHER.castles_key(old_castles, self.castle_rooks)
+                ^ crate::ZOBRIST_HASHER.castles_key(self.castles, self.castle_rooks);
+        }
+
+        // Update en passant square, which is only hashed if the opponent can capture onto it
+        if let Some(en_passant) = old_en_passant {
+            self.hash ^= crate::ZOBRIST_HASHER.en_passant_key(en_passant);
+        }
+        self.en_passant = match mv.move_type {
+            MoveType::FirstPawnMove => Some(mv.to.backward(self.side_to_move).unwrap()),
+            _ => None
+        };
+        if let Some(en_passant) = self.en_passant.filter(|&square| self.can_capture_en_passant(square, !self.side_to_move)) {
+            self.hash ^= crate::ZOBRIST_HASHER.en_passant_key(en_passant);
+        }
+
+        // Update halfmove count
+        self.halfmoves = if piece == Piece::Pawn || captured.is_some() || mv.move_type == MoveType::EnPassant {
+            0
+        } else {
+            self.halfmoves + 1
         };
-        let rank = match board.side_to_move {
-            Color::White => Rank::One,
-            Color::Black => Rank::Eight
+
+        // Update move counts
+        if self.side_to_move == Color::Black {
+            self.fullmove += 1;
+        }
+        self.ply += 1;
+
+        // Update turn
+        self.side_to_move = !self.side_to_move;
+        self.hash ^= crate::ZOBRIST_HASHER.side_to_move_key();
+
+        undo
+    }
+
+    pub fn make_null_move(&self) -> Board {
+        // Pass the turn without moving, for [null move pruning](https://www.chessprogramming.org/Null_Move_Pruning).
+        // The halfmove clock restarts, so that positions from before the pass don't count as repetitions after it
+        let mut board = *self;
+        if let Some(en_passant) = board.effective_en_passant() {
+            board.hash ^= crate::ZOBRIST_HASHER.en_passant_key(en_passant);
+        }
+        board.en_passant = None;
+        board.halfmoves = 0;
+        if board.side_to_move == Color::Black {
+            board.fullmove += 1;
+        }
+        board.ply += 1;
+        board.side_to_move = !board.side_to_move;
+        board.hash ^= crate::ZOBRIST_HASHER.side_to_move_key();
+        board
+    }
+
+    pub fn unmake(&mut self, undo: MoveUndoer) {
+        // Reverses `make_move_in_place` with the same xors
+        let mv = undo.mv;
+        self.side_to_move = !self.side_to_move;
+
+        let from_bb = Bitboard::from_square(mv.from);
+        let to_bb = Bitboard::from_square(mv.to);
+
+        let end_piece = self.get_piece_at(mv.to).unwrap();
+        let piece = match mv.move_type {
+            MoveType::Promotion(_) => Piece::Pawn,
+            _ => end_piece
         };
-        xor(&mut pieces, &mut colors, Bitboard::from_square(Square::from_coords(from_file, rank)), Piece::Rook, board.side_to_move);
-        xor(&mut pieces, &mut colors, Bitboard::from_square(Square::from_coords(to_file, rank)), Piece::Rook, board.side_to_move);
+
+        self.xor(to_bb, end_piece, self.side_to_move);
+        self.xor(from_bb, piece, self.side_to_move);
+        if let Some(captured) = undo.captured {
+            self.xor(to_bb, captured, !self.side_to_move);
+        }
+
+        if mv.is_castle() {
+            let (rook_from, rook_to) = self.castle_rook_squares(mv, self.side_to_move);
+            self.xor(Bitboard::from_square(rook_from), Piece::Rook, self.side_to_move);
+            self.xor(Bitboard::from_square(rook_to), Piece::Rook, self.side_to_move);
+        }
+
+        if mv.move_type == MoveType::EnPassant {
+            self.xor(Bitboard::from_square(
+                Square::from_coords(mv.to.file(), mv.from.rank())
+            ), Piece::Pawn, !self.side_to_move);
+        }
+
+        if self.side_to_move == Color::Black {
+            self.fullmove -= 1;
+        }
+        self.ply -= 1;
+
+        self.castles = undo.castles;
+        self.en_passant = undo.en_passant;
+        self.halfmoves = undo.halfmoves;
+        // The xors above toggled the piece keys back, but restoring is simpler than undoing the rest
+        self.hash = undo.hash;
     }
+}
 
-    // En passant capture
-    if mv.move_type == MoveType::EnPassant {
-        xor(&mut pieces, &mut colors, Bitboard::from_square(
-            Square::from_coords(mv.to.file(), mv.from.rank())
-        ), Piece::Pawn, !board.side_to_move);
+pub fn gen_legal_moves(board: &Board, v: &mut Vec<Move>) {
+    // Moves are added to the end of `v`, so reusing one cleared `Vec` avoids allocating
+    let start = v.len();
+    let blockers = board.blockers();
+
+    let legality = Legality::new(board);
+    if !legality.checkers.is_empty() {
+        gen_evasions(board, legality.checkers, blockers, v);
+    }
+    else {
+        for (square, piece) in board.piece_list(board.side_to_move) {
+            gen_piece_moves(board, piece, square, blockers, v);
+        }
     }
 
-    // Update turn
-    let side_to_move = !board.side_to_move;
+    legality.retain_allowed(board, v, start);
+}
 
-    // Update castles
-    const CASTLE_POINTS: Bitboard = Bitboard(
-        Bitboard::from_square(Square::A1).0 | Bitboard::from_square(Square::E1).0 | Bitboard::from_square(Square::H1).0 |
-        Bitboard::from_square(Square::A8).0 | Bitboard::from_square(Square::E8).0 | Bitboard::from_square(Square::H8).0
-    );
+fn gen_evasions(board: &Board, checkers: Bitboard, blockers: Bitboard, v: &mut Vec<Move>) {
+    // Only the moves that can get out of check: king moves, and in single check, capturing the checker
+    // or blocking it ([check evasions](https://www.chessprogramming.org/Check_Evasions))
+    let own = board.colors[board.side_to_move.idx()];
+    let king = (board.pieces[Piece::King.idx()] & own).to_square();
 
-    let mut castles = board.castles;
+    v.extend((king_attacks(king) & !own).map(|to| Move { from: king, to, move_type: MoveType::Basic }));
+    if checkers.count() > 1 {
+        return;
+    }
 
-    let move_bb = from_bb | to_bb;
-    if move_bb & CASTLE_POINTS != Bitboard::EMPTY {
-        if move_bb & Bitboard::from_square(Square::E1) != Bitboard::EMPTY {
-            castles.unset(Castle::WK);
-            castles.unset(Castle::WQ);
-        } else if move_bb & Bitboard::from_square(Square::E8) != Bitboard::EMPTY {
-            castles.unset(Castle::BK);
-            castles.unset(Castle::BQ);
+    let target = checkers | BETWEEN[king.idx()][checkers.to_square().idx()];
+    for piece in [Piece::Rook, Piece::Knight, Piece::Bishop, Piece::Queen] {
+        for square in board.pieces[piece.idx()] & own {
+            v.extend((gen_piece_attacks(piece, board.side_to_move, square, blockers) & target)
+                .map(|to| Move { from: square, to, move_type: MoveType::Basic })
+            );
         }
-        else {
-            if move_bb & Bitboard::from_square(Square::H1) != Bitboard::EMPTY {
-                castles.unset(Castle::WK);
-            }
-            if move_bb & Bitboard::from_square(Square::A1) != Bitboard::EMPTY {
-                castles.unset(Castle::WQ);
-            }
-            if move_bb & Bitboard::from_square(Square::H8) != Bitboard::EMPTY {
-                castles.unset(Castle::BK);
+    }
+
+    // Pawn moves come in too many kinds to mask up front. En passant can take a checking pawn without landing on it
+    let pawns_start = v.len();
+    for square in board.pieces[Piece::Pawn.idx()] & own {
+        gen_piece_moves(board, Piece::Pawn, square, blockers, v);
+    }
+    let mut kept = pawns_start;
+    for i in pawns_start..v.len() {
+        let mv = v[i];
+        if target.contains(mv.to) || mv.move_type == MoveType::EnPassant {
+            v[kept] = mv;
+            kept += 1;
+        }
+    }
+    v.truncate(kept);
+}
+
+pub fn legal_moves_from(board: &Board, square: Square, v: &mut Vec<Move>) {
+    // Only the legal moves of the piece on `square`, if it belongs to the side to move
+    if !board.colors[board.side_to_move.idx()].contains(square) { return; }
+    let Some(piece) = board.get_piece_at(square) else { return; };
+
+    let start = v.len();
+    gen_piece_moves(board, piece, square, board.blockers(), v);
+    retain_legal(board, v, start);
+}
+
+pub fn gen_captures(board: &Board, v: &mut Vec<Move>) {
+    // Only captures (including en passant) and promotions, for quiescence search and move ordering
+    let start = v.len();
+    let blockers = board.blockers();
+    let enemies = board.colors[(!board.side_to_move).idx()];
+
+    for (square, piece) in board.piece_list(board.side_to_move) {
+        gen_piece_captures(board, piece, square, blockers, enemies, v);
+    }
+
+    retain_legal(board, v, start);
+}
+
+fn retain_legal(board: &Board, v: &mut Vec<Move>, start: usize) {
+    // Legality check of the pseudolegal moves from `start` on, compacting them in place
+    Legality::new(board).retain_allowed(board, v, start);
+}
+
+// Pins and checks against the side to move's king, so most moves can be checked without making them
+// https://www.chessprogramming.org/Pin
+struct Legality {
+    king: Option<Square>,
+    checkers: Bitboard,
+    // Squares a non-king move must land on: everything when not in check, the checker and the squares
+    // between it and the king when in single check, and nothing when in double check
+    check_mask: Bitboard,
+    pinned: Bitboard,
+}
+
+impl Legality {
+    fn new(board: &Board) -> Self {
+        let stm = board.side_to_move;
+        let Some(king) = (board.pieces[Piece::King.idx()] & board.colors[stm.idx()]).next() else {
+            return Self { king: None, checkers: Bitboard::EMPTY, check_mask: !Bitboard::EMPTY, pinned: Bitboard::EMPTY };
+        };
+
+        let blockers = board.blockers();
+        let enemies = board.colors[(!stm).idx()];
+        let checkers = board.checkers();
+
+        let check_mask = match checkers.count() {
+            0 => !Bitboard::EMPTY,
+            1 => checkers | BETWEEN[king.idx()][checkers.to_square().idx()],
+            _ => Bitboard::EMPTY
+        };
+
+        // Enemy sliders that would attack the king if our own pieces were removed
+        let queens = board.pieces[Piece::Queen.idx()];
+        let snipers = enemies & (
+            magic_tables::get_rook_moves(king, enemies) & (board.pieces[Piece::Rook.idx()] | queens)
+            | magic_tables::get_bishop_moves(king, enemies) & (board.pieces[Piece::Bishop.idx()] | queens)
+        );
+
+        let mut pinned = Bitboard::EMPTY;
+        for sniper in snipers {
+            let in_between = BETWEEN[king.idx()][sniper.idx()] & blockers;
+            if in_between.count() == 1 {
+                pinned |= in_between;
             }
-            if move_bb & Bitboard::from_square(Square::A8) != Bitboard::EMPTY {
-                castles.unset(Castle::BQ);
+        }
+
+        Self { king: Some(king), checkers, check_mask, pinned }
+    }
+
+    fn retain_allowed(&self, board: &Board, v: &mut Vec<Move>, start: usize) {
+        let mut kept = start;
+        for i in start..v.len() {
+            let mv = v[i];
+            if self.allows(board, mv) {
+                v[kept] = mv;
+                kept += 1;
             }
         }
+        v.truncate(kept);
     }
 
-    // Update en passant square
-    let en_passant = match mv.move_type {
-        MoveType::FirstPawnMove => Some(mv.to.backward(board.side_to_move).unwrap()),
-        _ => None
-    };
+    #[inline]
+    fn allows(&self, board: &Board, mv: Move) -> bool {
+        let Some(king) = self.king else { return true; };
+
+        // King moves and en passant still need a full check. En passant removes two pawns from the same rank, so it
+        // can expose the king to a rook or queen along that rank even though neither pawn is pinned on its own,
+        // e.g. cxd6 is illegal in 8/8/8/K1Pp3r/8/8/8/k7 w - d6 0 1
+        if mv.from == king || mv.move_type == MoveType::EnPassant {
+            return leaves_king_safe(board, mv);
+        }
 
-    // Update halfmove count
-    let halfmoves = if piece == Piece::Pawn || captured.is_some() || mv.move_type == MoveType::EnPassant {
-        0
-    } else {
-        board.halfmoves + 1
-    };
+        self.check_mask.contains(mv.to) && (!self.pinned.contains(mv.from) || LINE[king.idx()][mv.from.idx()].contains(mv.to))
+    }
+}
 
-    Board {
-        pieces,
-        colors,
-        side_to_move,
-        castles,
-        en_passant,
-        halfmoves
+#[inline]
+fn leaves_king_safe(board: &Board, mv: Move) -> bool {
+    let board = make_move(board, mv);
+    match (board.pieces[Piece::King.idx()] & board.colors[(!board.side_to_move).idx()]).next() {
+        Some(king) => attackers_to(&board, king, board.side_to_move, board.blockers()).is_empty(),
+        None => true
     }
 }
 
-pub fn gen_legal_moves(board: &Board, v: &mut Vec<Move>) {
-    let mut pseudolegals = Vec::new();
-    let blockers = board.blockers();
+fn gen_piece_captures(board: &Board, piece: Piece, square: Square, blockers: Bitboard, enemies: Bitboard, v: &mut Vec<Move>) {
+    if piece != Piece::Pawn {
+        v.extend((gen_piece_attacks(piece, board.side_to_move, square, blockers) & enemies)
+            .map(|to| Move { from: square, to, move_type: MoveType::Basic })
+        );
+        return;
+    }
 
-    for piece in PIECES {
-        for square in board.pieces[piece.idx()] & board.colors[board.side_to_move.idx()] {
-            gen_piece_moves(board, piece, square, blockers, &mut pseudolegals);
+    let promotes = square.rank() == match board.side_to_move {
+        Color::White => Rank::Seven,
+        Color::Black => Rank::Two
+    };
+    let mut push = |to: Square, move_type: MoveType| push_pawn_move(square, to, move_type, promotes, v);
+
+    for capture in [
+        PAWN_LEFT_CAPTURES[board.side_to_move.idx()][square.idx()],
+        PAWN_RIGHT_CAPTURES[board.side_to_move.idx()][square.idx()]
+    ].into_iter().flatten() {
+        if enemies.contains(capture) {
+            push(capture, MoveType::Basic);
+        }
+        else if board.en_passant == Some(capture) {
+            push(capture, MoveType::EnPassant);
         }
     }
 
-    // Legality check
-    v.extend(pseudolegals.into_iter()
-        .filter(|&mv| {
-            let board = make_move(board, mv);
-            board.pieces[Piece::King.idx()] & board.colors[(!board.side_to_move).idx()]
-            & gen_attacks(&board, board.side_to_move, board.blockers()) == Bitboard::EMPTY
-        })
-    );
+    // Pushes only count if they promote
+    if promotes {
+        let fwd = square.forward(board.side_to_move).unwrap();
+        if !blockers.contains(fwd) {
+            push(fwd, MoveType::Basic);
+        }
+    }
+}
+
+#[inline]
+fn push_pawn_move(from: Square, to: Square, move_type: MoveType, promotes: bool, v: &mut Vec<Move>) {
+    // On the promotion rank, each move is added as its four promotions
+    if promotes {
+        v.extend(Move::promotions(from, to));
+    } else {
+        v.push(Move { from, to, move_type });
+    }
+}
+
+#[inline]
+const fn back_rank(color: Color) -> Rank {
+    match color {
+        Color::White => Rank::One,
+        Color::Black => Rank::Eight
+    }
+}
+
+#[inline]
+const fn rank_span(a: Square, b: Square) -> Bitboard {
+    // All squares from `a` to `b` inclusive, which must be on the same rank
+    let (lo, hi) = if a.idx() < b.idx() { (a.idx(), b.idx()) } else { (b.idx(), a.idx()) };
+    Bitboard((u64::MAX >> (63 - hi)) & (u64::MAX << lo))
 }
 
 fn gen_piece_moves(board: &Board, piece: Piece, square: Square, blockers: Bitboard, v: &mut Vec<Move>) {
     match piece {
         Piece::Rook => {
             v.extend(magic_tables::get_rook_moves(square, blockers)
-                .filter(|&to| board.colors[board.side_to_move.idx()] & Bitboard::from_square(to) == Bitboard::EMPTY)
+                .filter(|&to| !board.colors[board.side_to_move.idx()].contains(to))
                 .map(|to| Move { from: square, to, move_type: MoveType::Basic })
             );
         },
         Piece::Knight => {
-            v.extend(KNIGHT_MOVES[square.idx()]
-                .filter(|&to| board.colors[board.side_to_move.idx()] & Bitboard::from_square(to) == Bitboard::EMPTY)
+            v.extend(knight_attacks(square)
+                .filter(|&to| !board.colors[board.side_to_move.idx()].contains(to))
                 .map(|to| Move { from: square, to, move_type: MoveType::Basic })
             );
         },
         Piece::Bishop => {
             v.extend(magic_tables::get_bishop_moves(square, blockers)
-                .filter(|&to| board.colors[board.side_to_move.idx()] & Bitboard::from_square(to) == Bitboard::EMPTY)
+                .filter(|&to| !board.colors[board.side_to_move.idx()].contains(to))
                 .map(|to| Move { from: square, to, move_type: MoveType::Basic })
             );
         },
         Piece::Queen => {
             v.extend(magic_tables::get_queen_moves(square, blockers)
-                .filter(|&to| board.colors[board.side_to_move.idx()] & Bitboard::from_square(to) == Bitboard::EMPTY)
+                .filter(|&to| !board.colors[board.side_to_move.idx()].contains(to))
                 .map(|to| Move { from: square, to, move_type: MoveType::Basic })
             );
         },
         Piece::King => {
-            v.extend(KING_MOVES[square.idx()]
-                .filter(|&to| board.colors[board.side_to_move.idx()] & Bitboard::from_square(to) == Bitboard::EMPTY)
+            v.extend(king_attacks(square)
+                .filter(|&to| !board.colors[board.side_to_move.idx()].contains(to))
                 .map(|to| Move { from: square, to, move_type: MoveType::Basic })
             );
 
-            const CASTLE_WK_EMPTY: Bitboard = Bitboard(Bitboard::from_square(Square::F1).0 | Bitboard::from_square(Square::G1).0);
-            const CASTLE_WQ_EMPTY: Bitboard = Bitboard(Bitboard::from_square(Square::B1).0 | Bitboard::from_square(Square::C1).0 | Bitboard::from_square(Square::D1).0);
-            const CASTLE_BK_EMPTY: Bitboard = Bitboard(Bitboard::from_square(Square::F8).0 | Bitboard::from_square(Square::G8).0);
-            const CASTLE_BQ_EMPTY: Bitboard = Bitboard(Bitboard::from_square(Square::B8).0 | Bitboard::from_square(Square::C8).0 | Bitboard::from_square(Square::D8).0);
+            // The squares between the king, the rook and their destinations must be empty, and the king can't
+            // castle out of, through or into check. Computing these per castle also covers Chess960 setups
+            if square.rank() != back_rank(board.side_to_move)
+            || CASTLES.into_iter().all(|castle| castle.color() != board.side_to_move || !board.castles.is_set(castle)) {
+                return;
+            }
+            let attacks = gen_attacks(board, !board.side_to_move, blockers);
+            let rooks = board.pieces[Piece::Rook.idx()] & board.colors[board.side_to_move.idx()];
 
-            const CASTLE_WK_UNATTACKED: Bitboard = Bitboard(Bitboard::from_square(Square::E1).0 | Bitboard::from_square(Square::F1).0 | Bitboard::from_square(Square::G1).0);
-            const CASTLE_WQ_UNATTACKED: Bitboard = Bitboard(Bitboard::from_square(Square::C1).0 | Bitboard::from_square(Square::D1).0 | Bitboard::from_square(Square::E1).0);
-            const CASTLE_BK_UNATTACKED: Bitboard = Bitboard(Bitboard::from_square(Square::E8).0 | Bitboard::from_square(Square::F8).0 | Bitboard::from_square(Square::G8).0);
-            const CASTLE_BQ_UNATTACKED: Bitboard = Bitboard(Bitboard::from_square(Square::C8).0 | Bitboard::from_square(Square::D8).0 | Bitboard::from_square(Square::E8).0);
+            for castle in CASTLES {
+                if castle.color() != board.side_to_move || !board.castles.is_set(castle) { continue; }
 
-            let attacks = gen_attacks(board, !board.side_to_move, blockers);
+                let rook = board.castle_rooks[castle.idx()];
+                if !rooks.contains(rook) { continue; }
 
-            match board.side_to_move {
-                Color::White => {
-                    if board.castles.is_set(Castle::WK)
-                    && blockers & CASTLE_WK_EMPTY == Bitboard::EMPTY
-                    && attacks & CASTLE_WK_UNATTACKED == Bitboard::EMPTY {
-                        v.push(CASTLE_WK_MOVE);
-                    }
-                    if board.castles.is_set(Castle::WQ)
-                    && blockers & CASTLE_WQ_EMPTY == Bitboard::EMPTY
-                    && attacks & CASTLE_WQ_UNATTACKED == Bitboard::EMPTY {
-                        v.push(CASTLE_WQ_MOVE);
-                    }
-                },
-                Color::Black => {
-                    if board.castles.is_set(Castle::BK)
-                    && blockers & CASTLE_BK_EMPTY == Bitboard::EMPTY
-                    && attacks & CASTLE_BK_UNATTACKED == Bitboard::EMPTY {
-                        v.push(CASTLE_BK_MOVE);
-                    }
-                    if board.castles.is_set(Castle::BQ)
-                    && blockers & CASTLE_BQ_EMPTY == Bitboard::EMPTY
-                    && attacks & CASTLE_BQ_UNATTACKED == Bitboard::EMPTY {
-                        v.push(CASTLE_BQ_MOVE);
-                    }
+                let (king_to, rook_to) = castle.destinations();
+                let must_be_empty = (rank_span(square, king_to) | rank_span(rook, rook_to))
+                    & !Bitboard::from_square(square) & !Bitboard::from_square(rook);
+                let must_be_safe = rank_span(square, king_to);
+
+                if (blockers & must_be_empty).is_empty() && (attacks & must_be_safe).is_empty() {
+                    v.push(Move { from: square, to: king_to, move_type: MoveType::Castle(rook) });
                 }
             }
         },
         Piece::Pawn => {
-            let mut pawn_moves = Vec::new();
+            let promotes = square.rank() == match board.side_to_move {
+                Color::White => Rank::Seven,
+                Color::Black => Rank::Two
+            };
+            let mut push = |to: Square, move_type: MoveType| push_pawn_move(square, to, move_type, promotes, v);
+
             // Forward 1
             let fwd = square.forward(board.side_to_move).unwrap();
-            if blockers & Bitboard::from_square(fwd) == Bitboard::EMPTY {
-                pawn_moves.push(Move { from: square, to: fwd, move_type: MoveType::Basic });
+            if !blockers.contains(fwd) {
+                push(fwd, MoveType::Basic);
 
                 // Forward 2
                 if square.rank() == match board.side_to_move {
@@ -519,181 +1394,347 @@ fn gen_piece_moves(board: &Board, piece: Piece, square: Square, blockers: Bitboa
                 } {
                     let fwd_2 = square.forward(board.side_to_move).unwrap()
                                             .forward(board.side_to_move).unwrap();
-                    if blockers & Bitboard::from_square(fwd_2) == Bitboard::EMPTY {
-                        pawn_moves.push(Move { from: square, to: fwd_2, move_type: MoveType::FirstPawnMove });
+                    if !blockers.contains(fwd_2) {
+                        push(fwd_2, MoveType::FirstPawnMove);
                     }
                 }
             }
 
             // Capture left
             if let Some(capture) = PAWN_LEFT_CAPTURES[board.side_to_move.idx()][square.idx()] {
-                if board.colors[(!board.side_to_move).idx()] & Bitboard::from_square(capture) != Bitboard::EMPTY {
-                    pawn_moves.push(Move { from: square, to: capture, move_type: MoveType::Basic });
+                if board.colors[(!board.side_to_move).idx()].contains(capture) {
+                    push(capture, MoveType::Basic);
                 }
                 else if board.en_passant == Some(capture) {
-                    pawn_moves.push(Move { from: square, to: capture, move_type: MoveType::EnPassant });
+                    push(capture, MoveType::EnPassant);
                 }
             }
             // Capture right
             if let Some(capture) = PAWN_RIGHT_CAPTURES[board.side_to_move.idx()][square.idx()] {
-                if board.colors[(!board.side_to_move).idx()] & Bitboard::from_square(capture) != Bitboard::EMPTY {
-                    pawn_moves.push(Move { from: square, to: capture, move_type: MoveType::Basic });
+                if board.colors[(!board.side_to_move).idx()].contains(capture) {
+                    push(capture, MoveType::Basic);
                 }
                 else if board.en_passant == Some(capture) {
-                    pawn_moves.push(Move { from: square, to: capture, move_type: MoveType::EnPassant });
+                    push(capture, MoveType::EnPassant);
                 }
             }
-
-            // If on promotion rank, convert moves into promotions
-            if square.rank() == match board.side_to_move {
-                Color::White => Rank::Seven,
-                Color::Black => Rank::Two
-            } {
-                v.extend(pawn_moves.into_iter().flat_map(|mv| Move::promotions(mv.from, mv.to)));
-            } else {
-                v.extend(pawn_moves);
-            }
         }
     }
 }
 
+pub fn attackers_to(board: &Board, square: Square, by: Color, blockers: Bitboard) -> Bitboard {
+    // Pieces of color `by` attacking `square`. A piece attacks `square` exactly when the same kind of piece
+    // on `square` would attack it back, except that pawns capture in opposite directions for each color
+    let diagonal = board.pieces[Piece::Bishop.idx()] | board.pieces[Piece::Queen.idx()];
+    let orthogonal = board.pieces[Piece::Rook.idx()] | board.pieces[Piece::Queen.idx()];
+
+    board.colors[by.idx()] & (
+        pawn_attacks(square, !by) & board.pieces[Piece::Pawn.idx()]
+        | knight_attacks(square) & board.pieces[Piece::Knight.idx()]
+        | magic_tables::get_bishop_moves(square, blockers) & diagonal
+        | magic_tables::get_rook_moves(square, blockers) & orthogonal
+        | king_attacks(square) & board.pieces[Piece::King.idx()]
+    )
+}
+
 fn gen_attacks(board: &Board, color: Color, blockers: Bitboard) -> Bitboard {
     let mut attacks = Bitboard::EMPTY;
-    for piece in PIECES {
-        for square in board.pieces[piece.idx()] & board.colors[color.idx()] {
-            attacks |= gen_piece_attacks(piece, color, square, blockers);
-        }
+    for (square, piece) in board.piece_list(color) {
+        attacks |= gen_piece_attacks(piece, color, square, blockers);
     }
     attacks
 }
 
-fn gen_piece_attacks(piece: Piece, color: Color, square: Square, blockers: Bitboard) -> Bitboard {
+pub fn gen_piece_attacks(piece: Piece, color: Color, square: Square, blockers: Bitboard) -> Bitboard {
     match piece {
         Piece::Rook => magic_tables::get_rook_moves(square, blockers),
-        Piece::Knight => KNIGHT_MOVES[square.idx()],
+        Piece::Knight => knight_attacks(square),
         Piece::Bishop => magic_tables::get_bishop_moves(square, blockers),
         Piece::Queen => magic_tables::get_queen_moves(square, blockers),
-        Piece::King => KING_MOVES[square.idx()],
-        Piece::Pawn => {
-            (match square.forward(color).unwrap().left() {
-                Some(square) => Bitboard::from_square(square),
-                None => Bitboard::EMPTY
-            }) | match square.forward(color).unwrap().right() {
-                Some(square) => Bitboard::from_square(square),
-                None => Bitboard::EMPTY
-            }
-        }
+        Piece::King => king_attacks(square),
+        Piece::Pawn => pawn_attacks(square, color)
     }
 }
 
-const KNIGHT_MOVES: [Bitboard; NUM_SQUARES] = {
-    let mut knight_moves = [Bitboard::EMPTY; NUM_SQUARES];
-    let mut square_idx = 0;
-    while square_idx < NUM_SQUARES {
-        let square = Square::from_idx(square_idx);
-        let mut moves = Bitboard::EMPTY;
-
-        if let Some(step) = square.up() { if let Some(step) = step.up() { if let Some(sq) = step.left() {
-            moves.0 |= Bitboard::from_square(sq).0;
-        }}}
-        if let Some(step) = square.up() { if let Some(step) = step.up() { if let Some(sq) = step.right() {
-            moves.0 |= Bitboard::from_square(sq).0;
-        }}}
-        if let Some(step) = square.down() { if let Some(step) = step.down() { if let Some(sq) = step.left() {
-            moves.0 |= Bitboard::from_square(sq).0;
-        }}}
-        if let Some(step) = square.down() { if let Some(step) = step.down() { if let Some(sq) = step.right() {
-            moves.0 |= Bitboard::from_square(sq).0;
-        }}}
-        if let Some(step) = square.left() { if let Some(step) = step.left() { if let Some(sq) = step.up() {
-            moves.0 |= Bitboard::from_square(sq).0;
-        }}}
-        if let Some(step) = square.left() { if let Some(step) = step.left() { if let Some(sq) = step.down() {
-            moves.0 |= Bitboard::from_square(sq).0;
-        }}}
-        if let Some(step) = square.right() { if let Some(step) = step.right() { if let Some(sq) = step.up() {
-            moves.0 |= Bitboard::from_square(sq).0;
-        }}}
-        if let Some(step) = square.right() { if let Some(step) = step.right() { if let Some(sq) = step.down() {
-            moves.0 |= Bitboard::from_square(sq).0;
-        }}}
-
-        knight_moves[square_idx] = moves;
-        square_idx += 1;
-    }
-
-    knight_moves
-};
-
-const KING_MOVES: [Bitboard; NUM_SQUARES] = {
-    let mut king_moves = [Bitboard::EMPTY; NUM_SQUARES];
-    let mut square_idx = 0;
-    while square_idx < NUM_SQUARES {
-        let square = Square::from_idx(square_idx);
-        let mut moves = Bitboard::EMPTY;
-
-        if let Some(step) = square.up() { if let Some(sq) = step.left() {
-            moves.0 |= Bitboard::from_square(sq).0;
-        }}
-        if let Some(sq) = square.up() {
-            moves.0 |= Bitboard::from_square(sq).0;
-        }
-        if let Some(step) = square.up() { if let Some(sq) = step.right() {
-            moves.0 |= Bitboard::from_square(sq).0;
-        }}
-        if let Some(sq) = square.right() {
-            moves.0 |= Bitboard::from_square(sq).0;
-        }
-        if let Some(step) = square.down() { if let Some(sq) = step.right() {
-            moves.0 |= Bitboard::from_square(sq).0;
-        }}
-        if let Some(sq) = square.down() {
-            moves.0 |= Bitboard::from_square(sq).0;
-        }
-        if let Some(step) = square.down() { if let Some(sq) = step.left() {
-            moves.0 |= Bitboard::from_square(sq).0;
-        }}
-        if let Some(sq) = square.left() {
-            moves.0 |= Bitboard::from_square(sq).0;
-        }
-
-        king_moves[square_idx] = moves;
-        square_idx += 1;
-    }
-
-    king_moves
-};
-
-const PAWN_LEFT_CAPTURES: [[Option<Square>; NUM_SQUARES]; NUM_COLORS] = {
-    let mut captures = [[None; NUM_SQUARES]; NUM_COLORS];
-    let mut square_idx = 0;
-    while square_idx < NUM_SQUARES {
-        let square = Square::from_idx(square_idx);
-        match square.rank() {
-            Rank::One | Rank::Eight => { square_idx += 1; continue },
-            _ => ()
-        };
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn equality_ignores_fullmove_and_unusable_en_passant() {
+        magic_tables::init_magic_tables();
+        // Back where the game started, but with the halfmove clock at 4
+        let shuffled = apply_uci_moves(&Board::default(), &["g1f3", "g8f6", "f3g1", "f6g8"]).unwrap();
+        assert_eq!(shuffled, Board::new("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 4 1").unwrap());
+        assert_ne!(shuffled, Board::default());
+
+        // No black pawn can take on e3
+        let e4 = apply_uci_moves(&Board::default(), &["e2e4"]).unwrap();
+        let e4_fen = Board::new("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1").unwrap();
+        assert_eq!(e4, e4_fen);
+        assert!(HashSet::from([e4]).contains(&e4_fen));
+    }
 
-        captures[Color::White.idx()][square_idx] = square.up().unwrap().left();
-        captures[Color::Black.idx()][square_idx] = square.down().unwrap().left();
-        square_idx += 1;
-    }
-    captures
-};
-
-const PAWN_RIGHT_CAPTURES: [[Option<Square>; NUM_SQUARES]; NUM_COLORS] = {
-    let mut captures = [[None; NUM_SQUARES]; NUM_COLORS];
-    let mut square_idx = 0;
-    while square_idx < NUM_SQUARES {
-        let square = Square::from_idx(square_idx);
-        match square.rank() {
-            Rank::One | Rank::Eight => { square_idx += 1; continue },
-            _ => ()
-        };
+    #[test]
+    fn equality_includes_castling_rooks() {
+        magic_tables::init_magic_tables();
+        // The same pieces and castling rights, but with different rooks able to castle
+        let outer = Board::new("4k3/8/8/8/8/8/8/RR2K3 w A - 0 1").unwrap();
+        let inner = Board::new("4k3/8/8/8/8/8/8/RR2K3 w B - 0 1").unwrap();
+        assert_ne!(outer, inner);
+        assert_ne!(outer.get_hash(), inner.get_hash());
+        assert!(!HashSet::from([outer]).contains(&inner));
+
+        // Without castling rights, which rook would castle doesn't matter
+        let outer = make_move(&outer, outer.legal_move_from_uci("e1d1").unwrap());
+        let inner = make_move(&inner, inner.legal_move_from_uci("e1d1").unwrap());
+        assert_eq!(outer, inner);
+        assert_eq!(outer.get_hash(), inner.get_hash());
+    }
+
+    #[test]
+    fn fen_en_passant_round_trip() {
+        magic_tables::init_magic_tables();
+        let board = apply_uci_moves(&Board::default(), &["e2e4", "d7d5", "e4e5", "f7f5"]).unwrap();
+        let fen = "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3";
+        assert_eq!(board.get_fen(), fen);
+        assert_eq!(Board::new(fen).unwrap().get_fen(), fen);
+
+        // No black pawn can take on e3, so it's left out
+        let board = apply_uci_moves(&Board::default(), &["e2e4"]).unwrap();
+        assert_eq!(board.get_fen(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1");
+    }
+
+    #[test]
+    fn fen_rank_digits() {
+        magic_tables::init_magic_tables();
+        let with_rank = |rank: &str| format!("4k3/8/8/{rank}/8/8/8/4K3 w - - 0 1");
+        assert!(Board::try_from_fen(&with_rank("8")).is_ok());
+
+        // Adjacent digits, runs past the h-file and digits that aren't run lengths are all rejected
+        for rank in ["44", "71", "9"] {
+            assert_eq!(Board::try_from_fen(&with_rank(rank)).err(), Some(FenError::BadRank), "{rank}");
+        }
+    }
+
+    #[test]
+    fn fen_errors() {
+        magic_tables::init_magic_tables();
+        let cases = [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 ½", FenError::NotAscii),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0", FenError::WrongFieldCount),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 extra", FenError::WrongFieldCount),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w KQkq - 0 1", FenError::BadRank),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1", FenError::BadRank),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1", FenError::BadSideToMove),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQxq - 0 1", FenError::BadCastling),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e9 0 1", FenError::BadEnPassant),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x 1", FenError::BadHalfmove),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 256 1", FenError::BadHalfmove),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 x", FenError::BadFullmove),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 -1", FenError::BadFullmove),
+        ];
+        for (fen, err) in cases {
+            assert_eq!(Board::try_from_fen(fen).err(), Some(err), "{fen}");
+        }
+    }
+
+    #[test]
+    fn fen_without_move_counters() {
+        magic_tables::init_magic_tables();
+        let board = Board::new("r3k2r/8/8/8/8/8/8/R3K2R b Kq -").unwrap();
+        assert_eq!(board.halfmoves, 0);
+        assert_eq!(board.fullmove, 1);
+        assert_eq!(board.get_fen(), "r3k2r/8/8/8/8/8/8/R3K2R b Kq - 0 1");
+    }
+
+    #[test]
+    fn shredder_fen_round_trip() {
+        magic_tables::init_magic_tables();
+        // Rook files stay as written unless the rook is in its corner, which KQkq already describes
+        let fen = "1rqbkrbn/1ppppp1p/1n6/p1N3p1/8/2P4P/PP1PPPP1/1RQBKRBN w FBfb - 0 9";
+        assert_eq!(Board::new(fen).unwrap().get_fen(), fen);
+
+        let board = Board::new("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9").unwrap();
+        assert_eq!(board.get_fen(), "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KFkf - 2 9");
+        assert_eq!(Board::new(&board.get_fen()).unwrap(), board);
+    }
+
+    #[test]
+    fn chess960_perft() {
+        magic_tables::init_magic_tables();
+        // https://www.chessprogramming.org/Chess960_Perft_Results
+        let perft = |fen: &str, depth| crate::engine::perft_divide(&Board::new(fen).unwrap(), depth)
+            .into_iter().map(|(_, count)| count).sum::<usize>();
+        assert_eq!(perft("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", 4), 326672);
+        assert_eq!(perft("1rqbkrbn/1ppppp1p/1n6/p1N3p1/8/2P4P/PP1PPPP1/1RQBKRBN w FBfb - 0 9", 4), 287739);
+    }
+
+    #[test]
+    fn apply_uci_moves_reports_first_bad_move() {
+        magic_tables::init_magic_tables();
+        let mut board = Board::default();
+        let mut history = Vec::new();
+        // The king can't go to e2 while the pawn is still there
+        assert_eq!(board.apply_uci_moves_with_history(&["e2e4", "e7e5", "e1e3", "g8f6"], &mut history), Err(2));
+        assert_eq!(board, apply_uci_moves(&Board::default(), &["e2e4", "e7e5"]).unwrap());
+        let e4 = apply_uci_moves(&Board::default(), &["e2e4"]).unwrap();
+        assert_eq!(history, [Board::default().get_hash(), e4.get_hash()]);
+
+        // Malformed moves, and promotions without a piece
+        assert_eq!(apply_uci_moves(&Board::default(), &["e2e4", "e7"]), Err(1));
+        assert_eq!(apply_uci_moves(&Board::new("8/P6k/8/8/8/8/8/K7 w - - 0 1").unwrap(), &["a7a8"]), Err(0));
+    }
+
+    #[test]
+    fn repetition_count_resets_after_irreversible_moves() {
+        magic_tables::init_magic_tables();
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        let mut board = Board::default();
+        let mut history = Vec::new();
+        assert_eq!(board.repetition_count(&history), 1);
+
+        for count in 2..=4 {
+            board.apply_uci_moves_with_history(&shuffle, &mut history).unwrap();
+            assert_eq!(board.repetition_count(&history), count);
+        }
 
-        captures[Color::White.idx()][square_idx] = square.up().unwrap().right();
-        captures[Color::Black.idx()][square_idx] = square.down().unwrap().right();
-        square_idx += 1;
+        // A pawn move starts the count over, even though the earlier positions are still in the history
+        board.apply_uci_moves_with_history(&["e2e4", "d7d5"], &mut history).unwrap();
+        assert_eq!(board.repetition_count(&history), 1);
+        board.apply_uci_moves_with_history(&shuffle, &mut history).unwrap();
+        assert_eq!(board.repetition_count(&history), 2);
+
+        // And so does a capture
+        board.apply_uci_moves_with_history(&["e4d5"], &mut history).unwrap();
+        assert_eq!(board.repetition_count(&history), 1);
+        board.apply_uci_moves_with_history(&["b8c6", "g1f3", "c6b8", "f3g1"], &mut history).unwrap();
+        assert_eq!(board.repetition_count(&history), 2);
     }
-    captures
-};
\ No newline at end of file
+
+    #[test]
+    fn ply_counts_half_moves() {
+        magic_tables::init_magic_tables();
+        let mut board = Board::default();
+        assert_eq!(board.ply(), 0);
+
+        let mut undos = Vec::new();
+        for (i, uci) in ["e2e4", "e7e5", "g1f3", "b8c6", "f1b5"].into_iter().enumerate() {
+            undos.push(board.make_move_in_place(board.legal_move_from_uci(uci).unwrap()));
+            assert_eq!(board.ply(), i as u32 + 1);
+        }
+        assert_eq!(make_move(&board, board.legal_move_from_uci("a7a6").unwrap()).ply(), 6);
+        assert_eq!(board.make_null_move().ply(), 6);
+
+        while let Some(undo) = undos.pop() {
+            board.unmake(undo);
+            assert_eq!(board.ply(), undos.len() as u32);
+        }
+
+        // Set-up positions count from their fullmove number and side to move
+        assert_eq!(Board::new("4k3/8/8/8/8/8/8/4K3 b - - 0 10").unwrap().ply(), 19);
+        assert_eq!(Board::new("4k3/8/8/8/8/8/8/4K3 w - - 0 10").unwrap().ply(), 18);
+    }
+
+    #[test]
+    fn checkmate_beats_fifty_move_rule() {
+        magic_tables::init_magic_tables();
+        // Mate delivered on the 100th halfmove is still a win
+        let board = Board::new("R5k1/5ppp/8/8/8/8/8/6K1 b - - 100 80").unwrap();
+        assert_eq!(board.state(&[]), BoardState::WhiteWin);
+
+        // Without the mate, it's a draw
+        let board = Board::new("6k1/5ppp/8/8/8/8/8/R5K1 b - - 100 80").unwrap();
+        assert_eq!(board.state(&[]), BoardState::FiftyMoveRule);
+    }
+
+    #[test]
+    fn en_passant_rank_pin() {
+        magic_tables::init_magic_tables();
+        // Taking on d6 would take both pawns off the fifth rank, leaving the king in check from the rook
+        let board = Board::new("8/8/8/K1Pp3r/8/8/8/k7 w - d6 0 1").unwrap();
+        assert!(!board.is_legal_uci("c5d6"));
+
+        let mut moves = Vec::new();
+        gen_legal_moves(&board, &mut moves);
+        assert_eq!(moves.len(), 6);
+    }
+
+    fn assert_identical(board: &Board, expected: &Board) {
+        // Every field, unlike `==`, which ignores the ones that don't define the position
+        let Board { pieces, colors, side_to_move, castles, en_passant, halfmoves, fullmove, ply, castle_rooks, hash } = *board;
+        assert_eq!(pieces, expected.pieces);
+        assert_eq!(colors, expected.colors);
+        assert_eq!(side_to_move, expected.side_to_move);
+        assert_eq!(castles, expected.castles);
+        assert_eq!(en_passant, expected.en_passant);
+        assert_eq!(halfmoves, expected.halfmoves);
+        assert_eq!(fullmove, expected.fullmove);
+        assert_eq!(ply, expected.ply);
+        assert_eq!(castle_rooks, expected.castle_rooks);
+        assert_eq!(hash, expected.hash);
+        assert_eq!(board.get_hash(), crate::ZOBRIST_HASHER.hash(board));
+    }
+
+    #[test]
+    fn unmake_restores_board() {
+        magic_tables::init_magic_tables();
+        let starts = [
+            START_POS_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+        ];
+        let mut prng = crate::prng::PRNG::new(0x5EED);
+        let mut moves = Vec::new();
+
+        for i in 0..2000 {
+            let mut board = Board::new(starts[i % starts.len()]).unwrap();
+            let mut played = Vec::new();
+
+            // A random playout, then take every move back
+            for _ in 0..prng.next() % 60 {
+                moves.clear();
+                gen_legal_moves(&board, &mut moves);
+                if moves.is_empty() { break; }
+
+                let mv = moves[prng.next() as usize % moves.len()];
+                let before = board;
+                let undo = board.make_move_in_place(mv);
+                assert_identical(&board, &make_move(&before, mv));
+                played.push((before, undo));
+            }
+            while let Some((before, undo)) = played.pop() {
+                board.unmake(undo);
+                assert_identical(&board, &before);
+            }
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        fn round_trip<T: serde::Serialize + serde::de::DeserializeOwned>(value: &T) -> T {
+            serde_json::from_str(&serde_json::to_string(value).unwrap()).unwrap()
+        }
+
+        magic_tables::init_magic_tables();
+        // The second board has an en passant square that can be captured onto
+        for moves in [&["e2e4"][..], &["e2e4", "d7d5", "e4e5", "f7f5"]] {
+            let board = apply_uci_moves(&Board::default(), moves).unwrap();
+            assert_eq!(round_trip(&board), board);
+        }
+        assert_eq!(serde_json::to_string(&Board::default()).unwrap(), format!("\"{}\"", START_POS_FEN));
+
+        let board = Board::default();
+        let mv = board.legal_move_from_uci("g1f3").unwrap();
+        assert_eq!(round_trip(&mv), mv);
+        let square = Square::from_san("e4").unwrap();
+        assert_eq!(round_trip(&square), square);
+        assert_eq!(round_trip(&Piece::Knight), Piece::Knight);
+        assert_eq!(round_trip(&Color::Black), Color::Black);
+    }
+}
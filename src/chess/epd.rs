@@ -0,0 +1,65 @@
+// https://www.chessprogramming.org/Extended_Position_Description
+
+use super::board::Board;
+use super::mv::Move;
+
+#[derive(Debug, Clone)]
+pub struct Epd {
+    pub board: Board,
+    pub best_moves: Vec<Move>,
+    pub avoid_moves: Vec<Move>,
+    pub id: Option<String>,
+}
+
+pub fn parse_epd(line: &str) -> Option<Epd> {
+    // The first four FEN fields, then operations like `bm Nf3; id "WAC.001";`
+    // Operations other than `bm`, `am` and `id` are ignored
+    let line = line.trim();
+    let mut fields = line.splitn(5, ' ');
+    let [placement, side_to_move, castling, en_passant] = [fields.next()?, fields.next()?, fields.next()?, fields.next()?];
+    let operations = fields.next().unwrap_or_default();
+
+    let board = Board::new(&format!("{} {} {} {}", placement, side_to_move, castling, en_passant))?;
+
+    let mut epd = Epd { board, best_moves: Vec::new(), avoid_moves: Vec::new(), id: None };
+
+    for operation in split_operations(operations) {
+        let (opcode, operands) = operation.split_once(' ').unwrap_or((operation, ""));
+        let operands = operands.trim();
+
+        match opcode {
+            "bm" => for san in operands.split_whitespace() {
+                epd.best_moves.push(Move::from_san(san, &epd.board)?);
+            },
+            "am" => for san in operands.split_whitespace() {
+                epd.avoid_moves.push(Move::from_san(san, &epd.board)?);
+            },
+            "id" => epd.id = Some(operands.trim_matches('"').to_owned()),
+            _ => {}
+        }
+    }
+
+    Some(epd)
+}
+
+fn split_operations(operations: &str) -> Vec<&str> {
+    // Operations end with semicolons, which can also appear inside quoted strings
+    let mut split = Vec::new();
+    let mut start = 0;
+    let mut in_quotes = false;
+
+    for (i, char) in operations.char_indices() {
+        match char {
+            '"' => in_quotes = !in_quotes,
+            ';' if !in_quotes => {
+                split.push(operations[start..i].trim());
+                start = i + 1;
+            },
+            _ => {}
+        }
+    }
+    split.push(operations[start..].trim());
+
+    split.retain(|operation| !operation.is_empty());
+    split
+}
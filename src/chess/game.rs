@@ -0,0 +1,259 @@
+use super::board::{Board, BoardState, MoveUndoer, START_POS_FEN, gen_legal_moves, make_move};
+use super::color::Color;
+use super::mv::Move;
+
+const SEVEN_TAG_ROSTER: [(&str, &str); 7] = [
+    ("Event", "?"),
+    ("Site", "?"),
+    ("Date", "????.??.??"),
+    ("Round", "?"),
+    ("White", "?"),
+    ("Black", "?"),
+    ("Result", "*"),
+];
+
+const PGN_LINE_LENGTH: usize = 80;
+
+// The stateful counterpart to `Board`: a game from some starting position, with the moves played so far.
+// `Board` stays a cheap snapshot of one position, while `Game` owns the history needed for repetitions and PGNs
+#[derive(Debug, Clone)]
+pub struct Game {
+    start: Board,
+    board: Board,
+    moves: Vec<Move>,
+    // One per move played, to take it back
+    undos: Vec<MoveUndoer>,
+    // Hashes of the positions before `board`, oldest first, for repetition detection
+    position_history: Vec<u64>,
+}
+
+impl Game {
+    pub fn new(fen: &str) -> Option<Self> {
+        Board::new(fen).map(Self::from_board)
+    }
+
+    pub fn from_board(board: Board) -> Self {
+        Self { start: board, board, moves: Vec::new(), undos: Vec::new(), position_history: Vec::new() }
+    }
+
+    #[inline]
+    pub const fn board(&self) -> &Board {
+        &self.board
+    }
+
+    #[inline]
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    #[inline]
+    pub const fn start(&self) -> &Board {
+        &self.start
+    }
+
+    #[inline]
+    pub const fn fullmove(&self) -> u32 {
+        self.board.get_fullmove()
+    }
+
+    #[inline]
+    pub const fn ply(&self) -> u32 {
+        self.board.ply()
+    }
+
+    #[inline]
+    pub fn position_history(&self) -> &[u64] {
+        &self.position_history
+    }
+
+    pub fn play(&mut self, mv: Move) {
+        // `mv` must be legal in the current position
+        self.position_history.push(self.board.get_hash());
+        self.undos.push(self.board.make_move_in_place(mv));
+        self.moves.push(mv);
+    }
+
+    pub fn undo(&mut self) -> Option<Move> {
+        // Take back the last move, if there is one
+        let undo = self.undos.pop()?;
+        self.board.unmake(undo);
+        self.position_history.pop();
+        self.moves.pop()
+    }
+
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let mut moves = Vec::new();
+        gen_legal_moves(&self.board, &mut moves);
+        moves
+    }
+
+    pub fn state(&self) -> BoardState {
+        self.board.state(&self.position_history)
+    }
+
+    pub fn is_threefold_repetition(&self) -> bool {
+        self.board.repetition_count(&self.position_history) >= 3
+    }
+
+    pub fn to_fen(&self) -> String {
+        self.board.get_fen()
+    }
+
+    pub fn result(&self) -> &'static str {
+        match self.state() {
+            BoardState::Live => "*",
+            BoardState::WhiteWin => "1-0",
+            BoardState::BlackWin => "0-1",
+            _ => "1/2-1/2"
+        }
+    }
+
+    pub fn to_pgn(&self, tags: &[(String, String)]) -> String {
+        let result = self.result();
+        let mut pgn = String::new();
+
+        // Tags: the seven tag roster comes first and in order, followed by anything else
+        for (name, default) in SEVEN_TAG_ROSTER {
+            let value = match name {
+                "Result" => result,
+                _ => tags.iter().find(|(tag, _)| tag == name).map_or(default, |(_, value)| value.as_str())
+            };
+            pgn += &format!("[{} \"{}\"]\n", name, value);
+        }
+        // Games that don't start from the standard position need SetUp and FEN tags to be replayable
+        let fen = self.start.get_fen();
+        let custom_start = fen != START_POS_FEN;
+        if custom_start {
+            pgn += &format!("[SetUp \"1\"]\n[FEN \"{}\"]\n", fen);
+        }
+        for (name, value) in tags {
+            let derived = custom_start && (name == "SetUp" || name == "FEN");
+            if !derived && SEVEN_TAG_ROSTER.iter().all(|(tag, _)| tag != name) {
+                pgn += &format!("[{} \"{}\"]\n", name, value);
+            }
+        }
+        pgn.push('\n');
+
+        // Movetext
+        let mut tokens = Vec::new();
+        let mut board = self.start;
+        let mut move_num = board.get_fullmove();
+        for (i, &mv) in self.moves.iter().enumerate() {
+            match board.get_side_to_move() {
+                Color::White => tokens.push(format!("{}.", move_num)),
+                Color::Black if i == 0 => tokens.push(format!("{}...", move_num)),
+                Color::Black => {}
+            }
+            tokens.push(mv.to_san(&board));
+
+            if board.get_side_to_move().is_black() {
+                move_num += 1;
+            }
+            board = make_move(&board, mv);
+        }
+        tokens.push(result.to_owned());
+
+        let mut line_len = 0;
+        for token in tokens {
+            if line_len > 0 && line_len + 1 + token.len() > PGN_LINE_LENGTH {
+                pgn.push('\n');
+                line_len = 0;
+            }
+            if line_len > 0 {
+                pgn.push(' ');
+                line_len += 1;
+            }
+            line_len += token.len();
+            pgn += &token;
+        }
+        pgn.push('\n');
+
+        pgn
+    }
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::from_board(Board::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::init_magic_tables;
+
+    fn play(game: &mut Game, moves: &[&str]) {
+        for uci in moves {
+            game.play(game.board().legal_move_from_uci(uci).unwrap());
+        }
+    }
+
+    fn assert_pgn_round_trip(game: &Game) {
+        let pgn = game.to_pgn(&[("White".to_owned(), "Morphy".to_owned()), ("Annotator".to_owned(), "?".to_owned())]);
+        let (board, moves) = Board::from_pgn(&pgn).unwrap();
+        assert_eq!(moves, game.moves(), "{}", pgn);
+        assert_eq!(board.get_fen(), game.to_fen(), "{}", pgn);
+    }
+
+    #[test]
+    fn pgn_round_trip() {
+        init_magic_tables();
+        // Morphy's Opera Game, long enough to wrap the movetext
+        let mut game = Game::default();
+        play(&mut game, &[
+            "e2e4", "e7e5", "g1f3", "d7d6", "d2d4", "c8g4", "d4e5", "g4f3", "d1f3", "d6e5", "f1c4", "g8f6", "f3b3", "d8e7",
+            "b1c3", "c7c6", "c1g5", "b7b5", "c3b5", "c6b5", "c4b5", "b8d7", "e1c1", "a8d8", "d1d7", "d8d7", "h1d1", "e7e6",
+            "b5d7", "f6d7", "b3b8", "d7b8", "d1d8",
+        ]);
+        assert_eq!(game.result(), "1-0");
+        assert!(game.to_pgn(&[]).lines().all(|line| line.len() <= PGN_LINE_LENGTH));
+        assert_pgn_round_trip(&game);
+
+        // Unfinished, from a set-up position with black to move
+        let mut game = Game::new("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 3 20").unwrap();
+        play(&mut game, &["e8c8", "e1g1", "d8d1", "f1d1"]);
+        let pgn = game.to_pgn(&[]);
+        assert!(pgn.contains("[SetUp \"1\"]\n[FEN \"r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 3 20\"]\n"), "{}", pgn);
+        assert!(pgn.contains("20... O-O-O 21. O-O"), "{}", pgn);
+        assert_eq!(game.result(), "*");
+        assert_pgn_round_trip(&game);
+    }
+
+    #[test]
+    fn threefold_repetition_and_undo() {
+        init_magic_tables();
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        let mut game = Game::default();
+
+        // The start position occurs for the second time
+        play(&mut game, &shuffle);
+        assert!(!game.is_threefold_repetition());
+        assert_eq!(game.state(), BoardState::Live);
+        let board = *game.board();
+        let history = game.position_history().to_vec();
+
+        // And for the third
+        play(&mut game, &shuffle);
+        assert!(game.is_threefold_repetition());
+        assert_eq!(game.state(), BoardState::ThreefoldRepetition);
+        assert_eq!(game.result(), "1/2-1/2");
+        assert_eq!(game.moves().len(), 8);
+
+        for &uci in shuffle.iter().rev() {
+            assert_eq!(game.undo().map(|mv| mv.uci()).as_deref(), Some(uci));
+        }
+        assert_eq!(game.board().get_fen(), board.get_fen());
+        assert_eq!(game.board().get_hash(), board.get_hash());
+        assert_eq!(game.position_history(), history);
+        assert!(!game.is_threefold_repetition());
+
+        for _ in 0..4 {
+            game.undo();
+        }
+        assert_eq!(game.undo(), None);
+        assert_eq!(game.board().get_fen(), START_POS_FEN);
+        assert!(game.position_history().is_empty());
+        assert!(game.moves().is_empty());
+    }
+}
@@ -3,7 +3,6 @@ use super::{bitboard::Bitboard, square::{Square, NUM_SQUARES}};
 use std::sync::OnceLock;
 
 use rand::{RngCore, SeedableRng, rngs::SmallRng};
-use crate::prng::PRNG;
 
 // https://analog-hors.github.io/site/magic-bitboards/
 
@@ -28,107 +27,56 @@ static ROOK_MAGICS: OnceLock<[(Magic, Vec<Bitboard>); NUM_SQUARES]> = OnceLock::
 static BISHOP_MAGICS: OnceLock<[(Magic, Vec<Bitboard>); NUM_SQUARES]> = OnceLock::new();
 
 pub fn init_magic_tables() {
-    ROOK_MAGICS.set({
-        let mut magics = core::array::from_fn(|_|
-            (Magic {
-                mask: Bitboard::EMPTY,
-                mult: 0,
-                idx_bits: 0
-            },
-            Vec::with_capacity(1 << ROOK_IDX_BITS))
-        );
+    // Build the tables from the precomputed multipliers, only searching for new ones if validation fails.
+    // Safe to call more than once, including from several threads at a time; only the first call builds the tables
+    ROOK_MAGICS.get_or_init(|| core::array::from_fn(|square_idx|
+        init_magic(Square::from_idx(square_idx), ROOK_MASKS[square_idx], ROOK_IDX_BITS, ROOK_MULTS[square_idx], rook_moves)
+    ));
+    BISHOP_MAGICS.get_or_init(|| core::array::from_fn(|square_idx|
+        init_magic(Square::from_idx(square_idx), BISHOP_MASKS[square_idx], BISHOP_IDX_BITS, BISHOP_MULTS[square_idx], bishop_moves)
+    ));
+}
 
-        // TODO: improve my PRNG so this isn't needed
-        let mut rng = SmallRng::seed_from_u64(123123);
+fn init_magic(
+    square: Square, mask: Bitboard, idx_bits: u8, mult: u64, moves: fn(Square, Bitboard) -> Bitboard
+) -> (Magic, Vec<Bitboard>) {
+    let magic = Magic { mask, mult, idx_bits: 64 - idx_bits };
+    if let Some(moves_table) = build_moves_table(&magic, square, moves) {
+        return (magic, moves_table);
+    }
 
-        let mut square_idx = 0;
-        while square_idx < NUM_SQUARES {
-            let square = Square::from_idx(square_idx);
-            let mask = ROOK_MASKS[square_idx];
-
-            'search: loop {
-                let mult = rng.next_u64() & rng.next_u64() & rng.next_u64(); 
-                let magic = Magic { mask, mult, idx_bits: 64 - ROOK_IDX_BITS };
-
-                let mut moves_table = vec![Bitboard::EMPTY; 1 << ROOK_IDX_BITS];
-
-                let mut blockers = Bitboard::EMPTY;
-                loop {
-                    let moves = rook_moves(square, blockers);
-
-                    // Check if entry matches, or write entry to table
-                    let entry = &mut moves_table[magic_table_idx(&magic, blockers)];
-                    if entry.0 == Bitboard::EMPTY.0 {
-                        *entry = moves;
-                    } else if entry.0 != moves.0 {
-                        continue 'search;
-                    }
-
-                    // Move to next subset
-                    blockers.0 = blockers.0.wrapping_sub(mask.0) & mask.0;
-                    if blockers.0 == Bitboard::EMPTY.0 {
-                        break;
-                    }
-                }
-
-                magics[square_idx] = (magic, moves_table);
-                square_idx += 1;
-                break;
-            }
+    let mut rng = SmallRng::seed_from_u64(123123 ^ square.idx() as u64);
+    loop {
+        let mult = rng.next_u64() & rng.next_u64() & rng.next_u64();
+        let magic = Magic { mask, mult, idx_bits: 64 - idx_bits };
+        if let Some(moves_table) = build_moves_table(&magic, square, moves) {
+            return (magic, moves_table);
         }
+    }
+}
 
-        magics
-    }).map_err(|_| ()).expect("error initializing rook magics");
-    BISHOP_MAGICS.set({
-        let mut magics = core::array::from_fn(|_|
-            (Magic {
-                mask: Bitboard::EMPTY,
-                mult: 0,
-                idx_bits: 0
-            },
-            Vec::with_capacity(1 << BISHOP_IDX_BITS))
-        );
+fn build_moves_table(magic: &Magic, square: Square, moves: fn(Square, Bitboard) -> Bitboard) -> Option<Vec<Bitboard>> {
+    // Fill in the table for every subset of the mask, or return None if two subsets with different moves collide
+    let mut moves_table = vec![Bitboard::EMPTY; 1 << (64 - magic.idx_bits)];
 
-        let mut square_idx = 0;
-        while square_idx < NUM_SQUARES {
-            let square = Square::from_idx(square_idx);
-            let mask = BISHOP_MASKS[square_idx];
-
-            let mut prng = PRNG::new(123123);
-
-            'search: loop {
-                let mult = prng.next() & prng.next() & prng.next();
-                let magic = Magic { mask, mult, idx_bits: 64 - BISHOP_IDX_BITS };
-
-                let mut moves_table = vec![Bitboard::EMPTY; 1 << BISHOP_IDX_BITS];
-
-                let mut blockers = Bitboard::EMPTY;
-                loop {
-                    let moves = bishop_moves(square, blockers);
-
-                    // Check if entry matches, or write entry to table
-                    let entry = &mut moves_table[magic_table_idx(&magic, blockers)];
-                    if entry.0 == Bitboard::EMPTY.0 {
-                        *entry = moves;
-                    } else if entry.0 != moves.0 {
-                        continue 'search;
-                    }
-
-                    // Move to next subset
-                    blockers.0 = blockers.0.wrapping_sub(mask.0) & mask.0;
-                    if blockers.0 == Bitboard::EMPTY.0 {
-                        break;
-                    }
-                }
-
-                magics[square_idx] = (magic, moves_table);
-                square_idx += 1;
-                break;
-            }
+    let mut blockers = Bitboard::EMPTY;
+    loop {
+        let moves = moves(square, blockers);
+
+        // Check if entry matches, or write entry to table
+        let entry = &mut moves_table[magic_table_idx(magic, blockers)];
+        if entry.0 == Bitboard::EMPTY.0 {
+            *entry = moves;
+        } else if entry.0 != moves.0 {
+            return None;
         }
 
-        magics
-    }).map_err(|_| ()).expect("error initializing bishop magics");
+        // Move to next subset
+        blockers.0 = blockers.0.wrapping_sub(magic.mask.0) & magic.mask.0;
+        if blockers.0 == Bitboard::EMPTY.0 {
+            return Some(moves_table);
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -141,12 +89,31 @@ struct Magic {
 const fn magic_table_idx(magic: &Magic, blockers: Bitboard) -> usize {
     let blockers = blockers.0 & magic.mask.0;
     let hash = blockers.wrapping_mul(magic.mult);
-    let idx = (hash >> magic.idx_bits) as usize;
-    idx
+    (hash >> magic.idx_bits) as usize
 }
 
 const ROOK_IDX_BITS: u8 = 12;
 
+// Found with the search in `init_magic`
+const ROOK_MULTS: [u64; NUM_SQUARES] = [
+    0x9980004000988460, 0x02000940105020A0, 0x20200038000400A0, 0x011001104C002002,
+    0x4020010802100400, 0x1100140481000802, 0x0600008200204104, 0x2900010000448022,
+    0x4000802040808000, 0x01000640A08A0820, 0x8802282001002500, 0x1808008008450025,
+    0x860D080005400208, 0x1010900608008500, 0x0002300801428004, 0x0420500908208008,
+    0x0004040800589028, 0x20010A0040002180, 0x0082410804808400, 0x2064680100100828,
+    0x00804A0002205081, 0x1000030021001289, 0xC0F0220808809100, 0x0000020400441005,
+    0x0880001190002108, 0x1001002A10001080, 0x014200101000A420, 0x0100146410000807,
+    0x0400180080040042, 0x2481001010008002, 0x0400804480008006, 0x0492005020028202,
+    0x41C9808000180C80, 0x0004050008A02005, 0x0100804024220020, 0x082092100C040014,
+    0x0800060002001001, 0x04C0084068100620, 0x011000800108020A, 0x0010880822400182,
+    0x5008002042122000, 0x8000300082102008, 0x0A08507502001000, 0x080010000223000A,
+    0x4400100100910089, 0x0120080400050002, 0x86000A4320098808, 0x1800010040308008,
+    0x2040509150208200, 0x0040B12211085200, 0x0002002148040082, 0x0019800210208021,
+    0x0022004110100810, 0x0010218001252080, 0x41800111C04020C8, 0x9010106080410008,
+    0x0005002440188001, 0x700048204209228A, 0x0600200110064101, 0x0401104024092022,
+    0x0400020408001001, 0x0041000803840029, 0x008080815002081C, 0x002A0049008C0822,
+];
+
 const ROOK_MASKS: [Bitboard; NUM_SQUARES] = {
     let mut masks = [Bitboard::EMPTY; 64];
 
@@ -156,12 +123,7 @@ const ROOK_MASKS: [Bitboard; NUM_SQUARES] = {
         let mut mask = Bitboard::EMPTY;
 
         if let Some(mut sq) = square.up() {
-            loop {
-                let next = match sq.up() {
-                    Some(next) => next,
-                    None => break
-                };
-
+            while let Some(next) = sq.up() {
                 mask.0 |= Bitboard::from_square(sq).0;
 
                 sq = next;
@@ -169,12 +131,7 @@ const ROOK_MASKS: [Bitboard; NUM_SQUARES] = {
         }
 
         if let Some(mut sq) = square.down() {
-            loop {
-                let next = match sq.down() {
-                    Some(next) => next,
-                    None => break
-                };
-
+            while let Some(next) = sq.down() {
                 mask.0 |= Bitboard::from_square(sq).0;
 
                 sq = next;
@@ -182,12 +139,7 @@ const ROOK_MASKS: [Bitboard; NUM_SQUARES] = {
         }
 
         if let Some(mut sq) = square.left() {
-            loop {
-                let next = match sq.left() {
-                    Some(next) => next,
-                    None => break
-                };
-
+            while let Some(next) = sq.left() {
                 mask.0 |= Bitboard::from_square(sq).0;
 
                 sq = next;
@@ -195,12 +147,7 @@ const ROOK_MASKS: [Bitboard; NUM_SQUARES] = {
         }
 
         if let Some(mut sq) = square.right() {
-            loop {
-                let next = match sq.right() {
-                    Some(next) => next,
-                    None => break
-                };
-
+            while let Some(next) = sq.right() {
                 mask.0 |= Bitboard::from_square(sq).0;
 
                 sq = next;
@@ -216,6 +163,25 @@ const ROOK_MASKS: [Bitboard; NUM_SQUARES] = {
 
 const BISHOP_IDX_BITS: u8 = 9;
 
+const BISHOP_MULTS: [u64; NUM_SQUARES] = [
+    0x0030300121082440, 0x9802840060442000, 0x0008024450282040, 0x9802840060442000,
+    0x00A4120624010100, 0x00A4120624010100, 0x00A4120624010100, 0x9802840060442000,
+    0x06C108A006002020, 0x0008024450282040, 0x00A4120624010100, 0x9802840060442000,
+    0x9802840060442000, 0x00A4120624010100, 0x00A4120624010100, 0x00A4120624010100,
+    0x00A4120624010100, 0x0008024450282040, 0x04080401000C0010, 0x0030300121082440,
+    0x0002800108080020, 0x1062480050010448, 0x00A4120624010100, 0x00A4120624010100,
+    0x1062480050010448, 0x00A4120624010100, 0x400C00900102C100, 0x00040C0000401180,
+    0x8000840080802000, 0x0005408008920400, 0x00A4120624010100, 0x1109810202704020,
+    0x00A4120624010100, 0x00A4120624010100, 0x00A4120624010100, 0x0220040400080120,
+    0x8045070100100440, 0x1109810202704020, 0x1062480050010448, 0x1109810202704020,
+    0x00A4120624010100, 0x00A4120624010100, 0x0008024450282040, 0x0008000209000080,
+    0x1109810202704020, 0x0040021021204008, 0x0008024450282040, 0x0008024450282040,
+    0x00A4120624010100, 0x00A4120624010100, 0x1109810202704020, 0x00A4120624010100,
+    0x9802840060442000, 0x00A4120624010100, 0x9802840060442000, 0x9802840060442000,
+    0x9802840060442000, 0x00A4120624010100, 0x00A4120624010100, 0x80000080010201E0,
+    0x1062480050010448, 0x00A4120624010100, 0x06C108A006002020, 0x0030300121082440,
+];
+
 const BISHOP_MASKS: [Bitboard; NUM_SQUARES] = {
     let mut masks = [Bitboard::EMPTY; 64];
 
@@ -226,15 +192,10 @@ const BISHOP_MASKS: [Bitboard; NUM_SQUARES] = {
 
         if let Some(step) = square.up() {
         if let Some(mut sq) = step.left() {
-            loop {
-                let next = match sq.up() {
-                    Some(step) => match step.left() {
-                        Some(next) => next,
-                        None => break
-                    },
-                    None => break
-                };
-
+            while let Some(next) = match sq.up() {
+                Some(step) => step.left(),
+                None => None
+            } {
                 mask.0 |= Bitboard::from_square(sq).0;
 
                 sq = next;
@@ -243,15 +204,10 @@ const BISHOP_MASKS: [Bitboard; NUM_SQUARES] = {
 
         if let Some(step) = square.up() {
         if let Some(mut sq) = step.right() {
-            loop {
-                let next = match sq.up() {
-                    Some(step) => match step.right() {
-                        Some(next) => next,
-                        None => break
-                    },
-                    None => break
-                };
-
+            while let Some(next) = match sq.up() {
+                Some(step) => step.right(),
+                None => None
+            } {
                 mask.0 |= Bitboard::from_square(sq).0;
 
                 sq = next;
@@ -260,15 +216,10 @@ const BISHOP_MASKS: [Bitboard; NUM_SQUARES] = {
 
         if let Some(step) = square.down() {
         if let Some(mut sq) = step.left() {
-            loop {
-                let next = match sq.down() {
-                    Some(step) => match step.left() {
-                        Some(next) => next,
-                        None => break
-                    },
-                    None => break
-                };
-
+            while let Some(next) = match sq.down() {
+                Some(step) => step.left(),
+                None => None
+            } {
                 mask.0 |= Bitboard::from_square(sq).0;
 
                 sq = next;
@@ -277,15 +228,10 @@ const BISHOP_MASKS: [Bitboard; NUM_SQUARES] = {
 
         if let Some(step) = square.down() {
         if let Some(mut sq) = step.right() {
-            loop {
-                let next = match sq.down() {
-                    Some(step) => match step.right() {
-                        Some(next) => next,
-                        None => break
-                    },
-                    None => break
-                };
-
+            while let Some(next) = match sq.down() {
+                Some(step) => step.right(),
+                None => None
+            } {
                 mask.0 |= Bitboard::from_square(sq).0;
 
                 sq = next;
@@ -441,4 +387,21 @@ const fn bishop_moves(square: Square, blockers: Bitboard) -> Bitboard {
     }
 
     moves
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn precomputed_mults_are_magic() {
+        // The random search in `init_magic` should only ever be a fallback
+        for square_idx in 0..NUM_SQUARES {
+            let square = Square::from_idx(square_idx);
+            let rook = Magic { mask: ROOK_MASKS[square_idx], mult: ROOK_MULTS[square_idx], idx_bits: 64 - ROOK_IDX_BITS };
+            assert!(build_moves_table(&rook, square, rook_moves).is_some(), "rook on {}", square);
+            let bishop = Magic { mask: BISHOP_MASKS[square_idx], mult: BISHOP_MULTS[square_idx], idx_bits: 64 - BISHOP_IDX_BITS };
+            assert!(build_moves_table(&bishop, square, bishop_moves).is_some(), "bishop on {}", square);
+        }
+    }
+}
@@ -1,15 +1,18 @@
-use super::{board::Board, piece::Piece, square::{Rank, Square}};
+use super::{board::{Board, gen_legal_moves, make_move}, piece::Piece, square::{File, Rank, Square}};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MoveType {
     Basic,
     EnPassant,
-    Castle,
+    // Holds the castling rook's starting square, which Chess960 UCI notation needs
+    Castle(Square),
     FirstPawnMove,
     Promotion(Piece)
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Move {
     pub from: Square,
     pub to: Square,
@@ -22,6 +25,35 @@ impl Move {
         Move { from, to, move_type }
     }
 
+    #[inline]
+    pub fn is_capture(&self, board: &Board) -> bool {
+        // `board` is the position before the move is played
+        match self.move_type {
+            MoveType::EnPassant => true,
+            // A Chess960 king can land on its own rook's square
+            MoveType::Castle(_) => false,
+            _ => board.get_piece_at(self.to).is_some()
+        }
+    }
+
+    #[inline]
+    pub const fn is_castle(&self) -> bool {
+        matches!(self.move_type, MoveType::Castle(_))
+    }
+
+    #[inline]
+    pub const fn is_promotion(&self) -> Option<Piece> {
+        match self.move_type {
+            MoveType::Promotion(piece) => Some(piece),
+            _ => None
+        }
+    }
+
+    #[inline]
+    pub const fn is_en_passant(&self) -> bool {
+        matches!(self.move_type, MoveType::EnPassant)
+    }
+
     pub fn from_uci(uci: &str, board: &Board) -> Option<Self> {
         if !uci.is_ascii() || uci.len() < 4 { return None; }
 
@@ -30,11 +62,11 @@ impl Move {
 
         let move_type = match board.get_piece_at(from)? {
             Piece::Pawn => {
-                if let Some(ep) = board.get_en_passant() {
-                    if to == ep { MoveType::EnPassant } else { MoveType::Basic }
+                if board.get_en_passant() == Some(to) {
+                    MoveType::EnPassant
                 }
                 else if to.rank() == Rank::One || to.rank() == Rank::Eight {
-                    MoveType::Promotion(Piece::from_ascii(uci.bytes().nth(4)?)?)
+                    MoveType::Promotion(Piece::from_ascii(*uci.as_bytes().get(4)?)?)
                 }
                 else if from.rank() == Rank::Two && to.rank() == Rank::Four
                      || from.rank() == Rank::Seven && to.rank() == Rank::Five {
@@ -43,8 +75,15 @@ impl Move {
                 else { MoveType::Basic }
             },
             Piece::King => {
-                if uci == "e1g1" || uci == "e1c1" || uci == "e8g8" || uci == "e8c8" { MoveType::Castle }
-                else { MoveType::Basic }
+                // Chess960 castling is written as the king capturing its own rook
+                if board.get_piece_at(to) == Some(Piece::Rook) && board.get_color_at(to) == board.get_color_at(from) {
+                    let file = if to.idx() > from.idx() { File::G } else { File::C };
+                    return Some(Self { from, to: Square::from_coords(file, from.rank()), move_type: MoveType::Castle(to) });
+                }
+                match board.castle_rook(to) {
+                    Some(rook) if uci == "e1g1" || uci == "e1c1" || uci == "e8g8" || uci == "e8c8" => MoveType::Castle(rook),
+                    _ => MoveType::Basic
+                }
             },
             _ => MoveType::Basic
         };
@@ -52,10 +91,147 @@ impl Move {
         Some( Self { from, to, move_type } )
     }
 
+    pub fn from_san(san: &str, board: &Board) -> Option<Self> {
+        // Check/mate markers and annotations don't affect which move is meant
+        let san = san.trim_end_matches(['+', '#', '!', '?']);
+        if !san.is_ascii() || san.len() < 2 { return None; }
+
+        let mut moves = Vec::new();
+        gen_legal_moves(board, &mut moves);
+
+        // Castling
+        let castle_file = match san {
+            "O-O" | "0-0" => Some(File::G),
+            "O-O-O" | "0-0-0" => Some(File::C),
+            _ => None
+        };
+        if let Some(file) = castle_file {
+            return moves.into_iter().find(|mv| mv.is_castle() && mv.to.file() == file);
+        }
+
+        // Piece (pawn moves have no letter)
+        let (piece, san) = match san.as_bytes()[0] {
+            b'R' | b'N' | b'B' | b'Q' | b'K' => (Piece::from_ascii(san.as_bytes()[0])?, &san[1..]),
+            _ => (Piece::Pawn, san)
+        };
+
+        // Promotion
+        let (san, promotion) = match san.split_once('=') {
+            Some((san, promotion)) => {
+                let [b] = promotion.as_bytes() else { return None; };
+                (san, Some(Piece::from_ascii(*b)?))
+            },
+            None => (san, None)
+        };
+
+        // Destination square
+        if san.len() < 2 { return None; }
+        let to = Square::from_san(&san[san.len() - 2..])?;
+
+        // Disambiguation
+        let disambiguation = san[..san.len() - 2].trim_end_matches('x');
+        let mut from_file = None;
+        let mut from_rank = None;
+        for b in disambiguation.bytes() {
+            match b {
+                b'a'..=b'h' if from_file.is_none() && from_rank.is_none() => from_file = Some(File::from_ascii(b)),
+                b'1'..=b'8' if from_rank.is_none() => from_rank = Some(Rank::from_ascii(b)),
+                _ => return None
+            }
+        }
+
+        let mut candidates = moves.into_iter().filter(|mv| {
+            mv.to == to
+            && !mv.is_castle()
+            && board.get_piece_at(mv.from) == Some(piece)
+            && from_file.is_none_or(|file| mv.from.file() == file)
+            && from_rank.is_none_or(|rank| mv.from.rank() == rank)
+            && match mv.move_type {
+                MoveType::Promotion(p) => promotion == Some(p),
+                _ => promotion.is_none()
+            }
+        });
+
+        // Ambiguous moves are rejected
+        let mv = candidates.next()?;
+        if candidates.next().is_some() { return None; }
+        Some(mv)
+    }
+
+    pub fn to_san(&self, board: &Board) -> String {
+        // `board` is the position before the move is played
+        let mut san = String::new();
+
+        if self.is_castle() {
+            san += if self.to.file() == File::G { "O-O" } else { "O-O-O" };
+        }
+        else {
+            let piece = board.get_piece_at(self.from).expect("no piece on move origin");
+            let is_capture = self.is_capture(board);
+
+            if piece == Piece::Pawn {
+                if is_capture {
+                    san.push((self.from.file() as u8 + b'a') as char);
+                }
+            }
+            else {
+                san += &piece.to_string().to_uppercase();
+
+                // Disambiguate between pieces of the same type that can reach the same square
+                let mut moves = Vec::new();
+                gen_legal_moves(board, &mut moves);
+                let others = moves.into_iter()
+                    .filter(|mv| mv.to == self.to && mv.from != self.from && !mv.is_castle()
+                        && board.get_piece_at(mv.from) == Some(piece))
+                    .collect::<Vec<_>>();
+
+                if !others.is_empty() {
+                    let file = self.from.to_string();
+                    let (file, rank) = file.split_at(1);
+                    if others.iter().all(|mv| mv.from.file() != self.from.file()) {
+                        san += file;
+                    }
+                    else if others.iter().all(|mv| mv.from.rank() != self.from.rank()) {
+                        san += rank;
+                    }
+                    else {
+                        san += file;
+                        san += rank;
+                    }
+                }
+            }
+
+            if is_capture {
+                san.push('x');
+            }
+            san += &self.to.to_string();
+
+            if let Some(piece) = self.is_promotion() {
+                san.push('=');
+                san += &piece.to_string().to_uppercase();
+            }
+        }
+
+        // Check and checkmate
+        let next = make_move(board, *self);
+        if next.is_check() {
+            let mut replies = Vec::new();
+            gen_legal_moves(&next, &mut replies);
+            san.push(if replies.is_empty() { '#' } else { '+' });
+        }
+
+        san
+    }
+
     pub fn uci(&self) -> String {
+        // Castles that couldn't be standard chess are written as the king taking its own rook, as in Chess960 UCI
+        let to = match self.move_type {
+            MoveType::Castle(rook) if self.from.file() != File::E || !matches!(rook.file(), File::A | File::H) => rook,
+            _ => self.to
+        };
         format!("{}{}{}",
-            self.from.to_string(),
-            self.to.to_string(),
+            self.from,
+            to,
             if let MoveType::Promotion(piece) = self.move_type {
                 piece.to_string()
             } else {
@@ -77,4 +253,125 @@ impl std::fmt::Display for Move {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.uci())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::{init_magic_tables, START_POS_FEN};
+
+    fn san(fen: &str, uci: &str) -> String {
+        let board = Board::new(fen).unwrap();
+        board.legal_move_from_uci(uci).unwrap().to_san(&board)
+    }
+
+    fn from_san(fen: &str, san: &str) -> Option<String> {
+        Move::from_san(san, &Board::new(fen).unwrap()).map(|mv| mv.uci())
+    }
+
+    #[test]
+    fn san_disambiguation() {
+        init_magic_tables();
+        // Knights on b1 and f3 can both reach d2
+        let knights = "rnbqkbnr/pppppppp/8/8/3P4/5N2/PPP1PPPP/RNBQKB1R w KQkq - 0 1";
+        assert_eq!(san(knights, "b1d2"), "Nbd2");
+        assert_eq!(from_san(knights, "Nbd2").as_deref(), Some("b1d2"));
+        assert_eq!(from_san(knights, "Nfd2").as_deref(), Some("f3d2"));
+        assert_eq!(from_san(knights, "Nd2"), None);
+
+        // Rooks on the same file are told apart by rank
+        let rooks = "4k3/8/8/R7/8/8/8/R3K3 w - - 0 1";
+        assert_eq!(san(rooks, "a1a3"), "R1a3");
+        assert_eq!(san(rooks, "a5a3"), "R5a3");
+        assert_eq!(from_san(rooks, "R1a3").as_deref(), Some("a1a3"));
+        assert_eq!(from_san(rooks, "Ra3"), None);
+
+        // A rank is also accepted where the file would do
+        let rooks = "4k3/8/8/4R3/8/8/8/R5K1 w - - 0 1";
+        assert_eq!(san(rooks, "a1e1"), "Rae1+");
+        assert_eq!(from_san(rooks, "R1e1").as_deref(), Some("a1e1"));
+        assert_eq!(from_san(rooks, "Ree1").as_deref(), Some("e5e1"));
+    }
+
+    #[test]
+    fn san_castling_promotion_and_checks() {
+        init_magic_tables();
+        let castling = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        assert_eq!(san(castling, "e1g1"), "O-O");
+        assert_eq!(san(castling, "e1c1"), "O-O-O");
+        assert_eq!(from_san(castling, "O-O").as_deref(), Some("e1g1"));
+        assert_eq!(from_san(castling, "0-0-0").as_deref(), Some("e1c1"));
+
+        let promotion = "8/4P2k/8/8/8/8/8/K7 w - - 0 1";
+        assert_eq!(san(promotion, "e7e8q"), "e8=Q");
+        assert_eq!(san(promotion, "e7e8n"), "e8=N");
+        assert_eq!(from_san(promotion, "e8=Q").as_deref(), Some("e7e8q"));
+        assert_eq!(from_san(promotion, "e8"), None);
+
+        let mate = "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4";
+        assert_eq!(san(mate, "h5f7"), "Qxf7#");
+        assert_eq!(san(mate, "c4f7"), "Bxf7+");
+        assert_eq!(from_san(mate, "Qxf7#").as_deref(), Some("h5f7"));
+        assert_eq!(from_san(mate, "Qxf7").as_deref(), Some("h5f7"));
+        assert_eq!(from_san(mate, "Bxf7+!?").as_deref(), Some("c4f7"));
+    }
+
+    #[test]
+    fn san_rejects_illegal_moves() {
+        init_magic_tables();
+        for san in ["Ke2", "e5", "Nc4", "Nf6", "O-O", "Zf3", "e8=Q", "", "x"] {
+            assert_eq!(from_san(START_POS_FEN, san), None, "{}", san);
+        }
+    }
+
+    #[test]
+    fn san_round_trip() {
+        init_magic_tables();
+        for fen in [
+            START_POS_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        ] {
+            let board = Board::new(fen).unwrap();
+            let mut moves = Vec::new();
+            gen_legal_moves(&board, &mut moves);
+            for mv in moves {
+                let san = mv.to_san(&board);
+                assert_eq!(Move::from_san(&san, &board), Some(mv), "{} in {}", san, fen);
+            }
+        }
+    }
+
+    #[test]
+    fn chess960_castling_uci() {
+        init_magic_tables();
+        // Castles that look standard keep the king's destination, others are written as the king taking its rook
+        for (fen, uci, to) in [
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1g1", Square::G1),
+            ("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", "e8c8", Square::C8),
+            ("4k3/8/8/8/8/8/8/2R3KR w HC - 0 1", "g1h1", Square::G1),
+            ("4k3/8/8/8/8/8/8/2R3KR w HC - 0 1", "g1c1", Square::C1),
+            ("4k3/8/8/8/8/8/8/RK6 w A - 0 1", "b1a1", Square::C1),
+            ("1r2k3/8/8/8/8/8/8/4K3 b b - 0 1", "e8b8", Square::C8),
+        ] {
+            let board = Board::new(fen).unwrap();
+            let mv = board.legal_move_from_uci(uci).unwrap();
+            assert!(mv.is_castle(), "{uci} in {fen}");
+            assert_eq!(mv.to, to);
+            assert_eq!(mv.uci(), uci);
+        }
+
+        // Every castle in a Chess960 position prints as a move that reads back as itself
+        let board = Board::new("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9").unwrap();
+        let mut moves = Vec::new();
+        gen_legal_moves(&board, &mut moves);
+        for mv in moves {
+            assert_eq!(board.legal_move_from_uci(&mv.uci()), Some(mv));
+        }
+
+        // A king moving next to its rook isn't a castle
+        let board = Board::new("4k3/8/8/8/8/8/8/RK6 w A - 0 1").unwrap();
+        assert!(!board.legal_move_from_uci("b1c1").unwrap().is_castle());
+    }
+}
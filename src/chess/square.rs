@@ -141,9 +141,21 @@ impl Rank {
 
 #[repr(transparent)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Square(u8);
 
 pub const NUM_SQUARES: usize = 64;
+pub const SQUARES: [Square; NUM_SQUARES] = {
+    let mut squares = [Square(0); NUM_SQUARES];
+
+    let mut square_idx = 0;
+    while square_idx < NUM_SQUARES {
+        squares[square_idx] = Square(square_idx as u8);
+        square_idx += 1;
+    }
+
+    squares
+};
 
 impl Square {
     #[inline]
@@ -156,6 +168,12 @@ impl Square {
         Self(8 * rank as u8 + file as u8)
     }
 
+    // All squares from a1 to h8, rank by rank
+    #[inline]
+    pub fn iter() -> impl Iterator<Item = Square> {
+        SQUARES.into_iter()
+    }
+
     pub fn from_san(san: &str) -> Option<Self> {
         let bytes = san.as_bytes();
         if !san.is_ascii() || bytes.len() != 2
@@ -182,6 +200,12 @@ impl Square {
         self.0 as usize
     }
 
+    #[inline]
+    pub const fn is_light(&self) -> bool {
+        // Light squares are the ones whose file and rank indices add up to an odd number
+        (self.0 >> 3 ^ self.0) & 1 == 1
+    }
+
     #[inline]
     pub const fn up(&self) -> Option<Self> {
         match self.rank().up() {
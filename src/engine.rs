@@ -1,12 +1,65 @@
-use crate::chess::{Board, Color, Move, Piece, PIECES, gen_legal_moves, make_move};
+use crate::chess::{Bitboard, Board, Color, Epd, Move, MoveType, Piece, PIECES, NUM_PIECES, NUM_FILES, NUM_SQUARES, Square, gen_captures, gen_legal_moves, gen_piece_attacks, attackers_to, make_move};
+use crate::prng::PRNG;
 use crate::uci::{HaltCommand, UciGoOptions, UciResponse};
 
-use std::{collections::HashMap, sync::mpsc, time::Instant};
+use std::{collections::HashMap, sync::{atomic::{AtomicBool, Ordering}, mpsc, Arc}, thread, time::Instant};
 
 mod psts;
+mod tt;
+
+use tt::{NodeType, TtEntry};
+pub use tt::TranspositionTable;
 
 const MAX_DEPTH: usize = 6;
 const MAX_TIME: usize = usize::MAX; // ms
+const MOVE_OVERHEAD: usize = 50; // ms
+// How many nodes are searched between checks of the clock
+const TIME_CHECK_INTERVAL: usize = 2048;
+pub const DEFAULT_HASH_MB: usize = 16;
+pub const MAX_HASH_MB: usize = 4096;
+pub const DEFAULT_THREADS: usize = 1;
+pub const MAX_THREADS: usize = 256;
+pub const DEFAULT_CONTEMPT: isize = 0;
+pub const MAX_CONTEMPT: isize = 1000;
+pub const MAX_SKILL: u8 = 20;
+pub const DEFAULT_MULTI_PV: usize = 1;
+pub const MAX_MULTI_PV: usize = 256;
+// How many centipawns worse than the best move a move can be and still be picked, per skill level below the maximum
+const SKILL_MARGIN_STEP: isize = 10;
+// Null move pruning searches the reply to a pass this many plies shallower than a normal move,
+// and only at nodes with at least `NULL_MOVE_MIN_DEPTH` plies left
+const NULL_MOVE_REDUCTION: usize = 2;
+const NULL_MOVE_MIN_DEPTH: usize = 3;
+// Most check extensions along one branch, so long series of checks like perpetuals can't blow up the search
+const MAX_CHECK_EXTENSIONS: usize = 8;
+const MAX_PLY: usize = 64;
+// Deepest iteration, leaving room for the check extensions so no node is `MAX_PLY` or more plies from the root
+const MAX_SEARCH_DEPTH: usize = MAX_PLY - MAX_CHECK_EXTENSIONS - 1;
+
+const DRAW_SCORE: isize = 0;
+
+// Being mated `n` plies from the root scores `-(MATE - n)`, so shorter mates score higher
+const MATE: isize = 1_000_000;
+
+#[inline]
+const fn is_mate_score(score: isize) -> bool {
+    score.abs() >= MATE - MAX_PLY as isize
+}
+
+#[inline]
+const fn score_to_tt(score: isize, ply: usize) -> isize {
+    // Mate scores are stored relative to the node instead of the root
+    if !is_mate_score(score) { score }
+    else if score > 0 { score + ply as isize }
+    else { score - ply as isize }
+}
+
+#[inline]
+const fn score_from_tt(score: isize, ply: usize) -> isize {
+    if !is_mate_score(score) { score }
+    else if score > 0 { score - ply as isize }
+    else { score + ply as isize }
+}
 
 const fn next_iter_time_guess(depth: usize) -> usize {
     match depth {
@@ -25,9 +78,241 @@ pub struct SearchOptions {
     pub max_depth: usize,
     pub time: usize,
     pub nodes: Option<usize>,
+    pub hash_mb: usize,
+    pub threads: usize,
+    // How much the side to move at the root dislikes draws, in centipawns: draws score `-contempt` for it
+    // and `contempt` for its opponent, so positive values avoid draws and negative values seek them
+    pub contempt: isize,
+    // Whether to ponder, searching without limits until a `ponderhit` or `stop`
+    pub ponder: bool,
+    // Below `MAX_SKILL`, a random move close enough to the best one is played instead, chosen using `seed`
+    pub skill: u8,
+    pub seed: u64,
+    // How many of the best root moves to report lines for
+    pub multi_pv: usize,
+    pub eval_params: EvalParams,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Score {
+    // From the side to move's perspective
+    Centipawns(isize),
+    // Moves until mate, negative if the side to move is getting mated
+    Mate(isize),
+}
+
+impl Score {
+    const fn from_internal(score: isize) -> Self {
+        if !is_mate_score(score) { Score::Centipawns(score) }
+        else if score > 0 { Score::Mate((MATE - score + 1) / 2) }
+        else { Score::Mate(-(MATE + score) / 2) }
+    }
+}
+
+impl std::fmt::Display for Score {
+    // As in UCI `info` lines
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Score::Centipawns(cp) => write!(f, "cp {}", cp),
+            Score::Mate(moves) => write!(f, "mate {}", moves)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    // Can come from an unfinished iteration; the other fields are from the last completed one (`depth` is 0 if there wasn't one).
+    // `None` if there are no legal moves, with `score` being `Mate(0)` for checkmate and `Centipawns(0)` for stalemate,
+    // or if the search was stopped before its first iteration finished
+    pub best_move: Option<Move>,
+    pub score: Score,
+    pub depth: usize,
+    pub pv: Vec<Move>,
+    pub nodes: usize,
+    // The score and principal variation of each of the best `multi_pv` root moves, best first
+    pub lines: Vec<(Score, Vec<Move>)>,
 }
 
-pub fn decide_options(board: &mut Board, go_options: &UciGoOptions) -> SearchOptions {
+impl SearchResult {
+    fn complete_iteration(&mut self, board: &Board, depth: usize, score: isize, ctx: &SearchContext, multi_pv: usize) {
+        self.score = Score::from_internal(score);
+        self.depth = depth;
+        self.pv = match self.best_move {
+            Some(best_move) => ctx.principal_variation(board, best_move, depth),
+            None => Vec::new()
+        };
+        self.nodes = ctx.nodes;
+        // With more than one line, every root move was scored with a full window, so the others' scores are exact too
+        self.lines = if multi_pv > 1 {
+            ctx.root_scores.iter().take(multi_pv)
+                .map(|&(mv, score)| (Score::from_internal(score), ctx.principal_variation(board, mv, depth)))
+                .collect()
+        } else {
+            vec![(self.score, self.pv.clone())]
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EngineConfig {
+    // Settings that persist between searches, set through UCI options
+    pub hash_mb: usize,
+    pub threads: usize,
+    pub contempt: isize,
+    pub skill: u8,
+    // Mixed with each position's hash to seed the move choice below full skill
+    pub seed: u64,
+    pub multi_pv: usize,
+    pub eval_params: EvalParams,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            hash_mb: DEFAULT_HASH_MB,
+            threads: DEFAULT_THREADS,
+            contempt: DEFAULT_CONTEMPT,
+            skill: MAX_SKILL,
+            seed: 0,
+            multi_pv: DEFAULT_MULTI_PV,
+            eval_params: DEFAULT_EVAL_PARAMS,
+        }
+    }
+}
+
+struct SearchContext {
+    // Per-thread search state threaded through the recursion
+    tt: Arc<TranspositionTable>,
+    killers: [[Option<Move>; 2]; MAX_PLY],
+    nodes: usize,
+    node_limit: Option<usize>,
+    // Milliseconds after `start_time` at which the search is cut off, even in the middle of an iteration
+    time_limit: Option<usize>,
+    // Hashes of the positions leading up to the current node, starting with the game history
+    path: Vec<u64>,
+    // Length of `path` at the root: the game history and the root position
+    root_path_len: usize,
+    // Set by the main search thread to end helper threads
+    stop: Arc<AtomicBool>,
+    contempt: isize,
+    eval_params: EvalParams,
+    start_time: Instant,
+    // Cleared move lists to reuse, so nodes don't have to allocate their own
+    move_lists: Vec<Vec<Move>>,
+    // Whether the current node is below a null move, where another one isn't tried
+    in_null_move: bool,
+    // Depth of the current iteration, to count the extensions along a branch
+    root_depth: usize,
+    // Every root move's score from the last completed `dfs_search_and_sort`, best first
+    root_scores: Vec<(Move, isize)>,
+}
+
+impl SearchContext {
+    fn new(tt: Arc<TranspositionTable>, stop: Arc<AtomicBool>, board: &Board, history: &[u64]) -> Self {
+        let mut path = history.to_vec();
+        path.push(board.get_hash());
+
+        Self {
+            tt,
+            killers: [[None; 2]; MAX_PLY],
+            nodes: 0,
+            node_limit: None,
+            time_limit: None,
+            root_path_len: path.len(),
+            path,
+            stop,
+            contempt: DEFAULT_CONTEMPT,
+            eval_params: DEFAULT_EVAL_PARAMS,
+            start_time: Instant::now(),
+            move_lists: Vec::new(),
+            in_null_move: false,
+            root_depth: 0,
+            root_scores: Vec::new(),
+        }
+    }
+
+    #[inline]
+    fn draw_score(&self, ply: usize) -> isize {
+        // Even plies have the root's side to move
+        if ply.is_multiple_of(2) { DRAW_SCORE - self.contempt } else { DRAW_SCORE + self.contempt }
+    }
+
+    #[inline]
+    fn is_repetition(&self, hash: u64, halfmoves: u8) -> bool {
+        // Positions can only repeat since the last irreversible move, and only with the same side to move
+        self.path.iter().rev().take(halfmoves as usize).skip(1).step_by(2).any(|&h| h == hash)
+    }
+
+    fn unwind_path(&mut self) {
+        // A halted search returns without popping the positions it was in the middle of,
+        // so they have to be cleared before searching from the root again
+        self.path.truncate(self.root_path_len);
+    }
+
+    #[inline]
+    fn take_move_list(&mut self) -> Vec<Move> {
+        self.move_lists.pop().unwrap_or_default()
+    }
+
+    #[inline]
+    fn return_move_list(&mut self, mut moves: Vec<Move>) {
+        moves.clear();
+        self.move_lists.push(moves);
+    }
+
+    #[inline]
+    fn count_node(&mut self) -> Result<(), HaltCommand> {
+        // Running out of nodes or time, or being told to stop by the main thread, ends the search the same way a `stop` command does
+        self.nodes += 1;
+        match (self.node_limit, self.time_limit) {
+            (Some(limit), _) if self.nodes >= limit => Err(HaltCommand::Stop),
+            (_, Some(limit)) if self.nodes.is_multiple_of(TIME_CHECK_INTERVAL)
+                && self.start_time.elapsed().as_millis() as usize >= limit => Err(HaltCommand::Stop),
+            _ if self.stop.load(Ordering::Relaxed) => Err(HaltCommand::Stop),
+            _ => Ok(())
+        }
+    }
+
+    fn principal_variation(&self, board: &Board, best_move: Move, max_len: usize) -> Vec<Move> {
+        // Follow the best moves stored in the transposition table
+        let mut pv = vec![best_move];
+        let mut board = make_move(board, best_move);
+        while pv.len() < max_len {
+            let Some(mv) = self.tt.probe(board.get_hash()).and_then(|entry| entry.best_move) else { break; };
+
+            // Entries can be overwritten or collide, so make sure the move is actually playable here
+            let mut moves = Vec::new();
+            gen_legal_moves(&board, &mut moves);
+            if !moves.contains(&mv) { break; }
+
+            pv.push(mv);
+            board = make_move(&board, mv);
+        }
+        pv
+    }
+
+    #[inline]
+    fn killer_score(&self, mv: Move, ply: usize) -> isize {
+        match self.killers.get(ply) {
+            Some(&[first, _]) if first == Some(mv) => 2,
+            Some(&[_, second]) if second == Some(mv) => 1,
+            _ => 0
+        }
+    }
+
+    #[inline]
+    fn store_killer(&mut self, mv: Move, ply: usize) {
+        // Two slots per ply, most recent first
+        if let Some(killers) = self.killers.get_mut(ply) {
+            if killers[0] != Some(mv) {
+                killers[1] = killers[0];
+                killers[0] = Some(mv);
+            }
+        }
+    }
+}
+
+pub fn decide_options(board: &Board, go_options: &UciGoOptions, config: &EngineConfig) -> SearchOptions {
     let time;
     if let Some(move_time) = go_options.move_time {
         time = move_time;
@@ -42,7 +327,11 @@ pub fn decide_options(board: &mut Board, go_options: &UciGoOptions) -> SearchOpt
         }.unwrap_or_default();
 
         // https://www.chessprogramming.org/Time_Management#Time_Controls
-        time = clock_time / 20 + increment / 2;
+        // With `movestogo`, the clock is split between the moves left until the next time control (plus one to spare),
+        // otherwise the game is assumed to last another 20 moves
+        let moves_left = go_options.moves_to_go.map_or(20, |moves_to_go| moves_to_go.saturating_add(1));
+        // Never use more than is left on the clock, keeping a margin for communication delays
+        time = (clock_time / moves_left + increment / 2).min(clock_time.saturating_sub(MOVE_OVERHEAD));
     }
     else {
         time = MAX_TIME;
@@ -60,7 +349,8 @@ pub fn decide_options(board: &mut Board, go_options: &UciGoOptions) -> SearchOpt
         }
         depth - 1
     };
-    let max_depth = go_options.depth.unwrap_or(MAX_DEPTH).min(time_bound_depth).min(MAX_DEPTH);
+    // Even with almost no time left, a depth 1 search is needed to have a move to play
+    let max_depth = go_options.depth.unwrap_or(MAX_DEPTH).min(time_bound_depth).clamp(1, MAX_DEPTH);
 
     let nodes = go_options.nodes;
 
@@ -68,35 +358,151 @@ pub fn decide_options(board: &mut Board, go_options: &UciGoOptions) -> SearchOpt
         max_depth,
         time,
         nodes,
+        hash_mb: config.hash_mb,
+        threads: config.threads,
+        contempt: config.contempt,
+        ponder: go_options.ponder,
+        skill: config.skill,
+        seed: config.seed ^ board.get_hash(),
+        multi_pv: config.multi_pv,
+        eval_params: config.eval_params,
     }
 }
 
 pub fn search_perft(board: &Board, depth: usize, info_sender: Option<&mpsc::Sender<UciResponse>>) -> usize {
     if depth == 0 { return 1; }
 
-    let mut count = 0;
-
     if let Some(info_sender) = info_sender {
-        let mut moves = Vec::new();
-        gen_legal_moves(board, &mut moves);
-
-        for mv in moves {
-            let mut subtotal = 0;
-            perft(&make_move(board, mv), &mut subtotal, depth - 1);
-
+        let divide = perft_divide(board, depth);
+        for &(mv, subtotal) in &divide {
             info_sender.send(UciResponse::Plaintext(format!("{}: {}", mv.uci(), subtotal))).expect("stdout error");
-
-            count += subtotal;
         }
+        divide.iter().map(|&(_, subtotal)| subtotal).sum()
     }
     else {
-        perft(board, &mut count, depth)
+        let mut count = 0;
+        perft(&mut board.clone(), &mut count, depth);
+        count
+    }
+}
+
+pub fn perft_divide(board: &Board, depth: usize) -> Vec<(Move, usize)> {
+    // The perft count after each root move, sorted by the moves' UCI strings to line up with other engines' output
+    if depth == 0 { return Vec::new(); }
+
+    let mut board = *board;
+    let mut moves = Vec::new();
+    gen_legal_moves(&board, &mut moves);
+
+    let mut divide = moves.into_iter().map(|mv| {
+        let mut subtotal = 0;
+        let undo = board.make_move_in_place(mv);
+        perft(&mut board, &mut subtotal, depth - 1);
+        board.unmake(undo);
+        (mv, subtotal)
+    }).collect::<Vec<_>>();
+
+    divide.sort_by_cached_key(|(mv, _)| mv.uci());
+    divide
+}
+
+pub fn perft_hashed(board: &Board, depth: usize) -> usize {
+    // Perft that remembers the count below each position it has seen, so transpositions are only counted once.
+    // Much faster at higher depths, but a hash collision could make the count wrong, so use plain perft to check move generation
+    let mut cache = HashMap::new();
+    perft_cached(&mut board.clone(), depth, &mut cache)
+}
+
+fn perft_cached(board: &mut Board, depth: usize, cache: &mut HashMap<(u64, usize), usize>) -> usize {
+    let mut moves = Vec::new();
+    gen_legal_moves(board, &mut moves);
+
+    // Subtrees this small are faster to count than to look up
+    if depth <= 1 {
+        return if depth == 0 { 1 } else { moves.len() };
+    }
+    if let Some(&count) = cache.get(&(board.get_hash(), depth)) {
+        return count;
+    }
+
+    let mut count = 0;
+    for mv in moves {
+        let undo = board.make_move_in_place(mv);
+        count += perft_cached(board, depth - 1, cache);
+        board.unmake(undo);
     }
 
+    cache.insert((board.get_hash(), depth), count);
     count
 }
 
-fn perft(board: &Board, count: &mut usize, depth: usize) {
+pub fn perft_nps(board: &Board, depth: usize) -> (usize, usize) {
+    // Returns the perft node count and how many nodes were counted per second, as a move generation benchmark
+    let start_time = Instant::now();
+    let count = search_perft(board, depth, None);
+    let nps = (count as f64 / start_time.elapsed().as_secs_f64()) as usize;
+    (count, nps)
+}
+
+pub fn bench() -> (usize, usize) {
+    // Searches a fixed set of positions to a fixed depth, returning the total node count and nodes per second.
+    // Keep the positions and depth unchanged so results can be compared across commits.
+    let options = SearchOptions {
+        max_depth: BENCH_DEPTH,
+        time: MAX_TIME,
+        nodes: None,
+        hash_mb: DEFAULT_HASH_MB,
+        threads: 1,
+        contempt: DEFAULT_CONTEMPT,
+        ponder: false,
+        skill: MAX_SKILL,
+        seed: 0,
+        multi_pv: DEFAULT_MULTI_PV,
+        eval_params: DEFAULT_EVAL_PARAMS,
+    };
+
+    let start_time = Instant::now();
+    let mut nodes = 0;
+    for fen in BENCH_FENS {
+        let board = Board::new(fen).expect("bench positions are valid");
+        nodes += search_with_info(&board, options).nodes;
+    }
+    let nps = (nodes as f64 / start_time.elapsed().as_secs_f64()) as usize;
+    (nodes, nps)
+}
+
+pub fn solves_epd(epd: &Epd, options: SearchOptions) -> bool {
+    // Whether the search plays one of an EPD position's best moves (any move, if it has none) and none of its moves to avoid
+    let Some(mv) = search_with_info(&epd.board, options).best_move else { return false; };
+    (epd.best_moves.is_empty() || epd.best_moves.contains(&mv)) && !epd.avoid_moves.contains(&mv)
+}
+
+pub fn run_epd_suite(epds: &[Epd], options: SearchOptions) -> usize {
+    // How many positions of a test suite like [WAC](https://www.chessprogramming.org/Win_at_Chess) the search solves
+    epds.iter().filter(|epd| solves_epd(epd, options)).count()
+}
+
+const BENCH_DEPTH: usize = 5;
+
+const BENCH_FENS: [&str; 15] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 10",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 11",
+    "4rrk1/pp1n3p/3q2pQ/2p1pb2/2PP4/2P3N1/P2B2PP/4RRK1 b - - 7 19",
+    "rq3rk1/ppp2ppp/1bnpb3/3N2B1/3NP3/7P/PPPQ1PP1/2KR3R w - - 7 14",
+    "r1bq1r1k/1pp1n1pp/1p1p4/4p2Q/4Pp2/1BNP4/PPP2PPP/3R1RK1 w - - 2 14",
+    "r3r1k1/2p2ppp/p1p1bn2/8/1q2P3/2NPQN2/PPP3PP/R4RK1 b - - 2 15",
+    "r1bbk1nr/pp3p1p/2n5/1N4p1/2Np1B2/8/PPP2PPP/2KR1B1R w kq - 0 13",
+    "r1bq1rk1/ppp1nppp/4n3/3p3Q/3P4/1BP1B3/PP1N2PP/R4RK1 w - - 1 16",
+    "4r1k1/r1q2ppp/ppp2n2/4P3/5Rb1/1N1BQ3/PPP3PP/R5K1 w - - 1 17",
+    "2rqkb1r/ppp2p2/2npb1p1/1N1Nn2p/2P1PP2/8/PP2B1PP/R1BQK2R b KQ - 0 11",
+    "r1bq1r1k/b1p1npp1/p2p3p/1p6/3PP3/1B2NN2/PP3PPP/R2Q1RK1 w - - 1 16",
+    "3r1rk1/p5pp/bpp1pp2/8/q1PP1P2/b3P3/P2NQRPP/1R2B1K1 b - - 6 22",
+    "4k2r/1pb2ppp/1p2p3/1R1p4/3P4/2r1PN2/P4PPP/1R4K1 b - - 3 22",
+    "3q2k1/pb3p1p/4pbp1/2r5/PpN2N2/1P2P2P/5PP1/Q2R2K1 b - - 4 26",
+];
+
+fn perft(board: &mut Board, count: &mut usize, depth: usize) {
     if depth == 0 {
         *count += 1;
         return;
@@ -111,121 +517,407 @@ fn perft(board: &Board, count: &mut usize, depth: usize) {
     }
 
     for mv in moves {
-        perft(&make_move(board, mv), count, depth - 1);
+        let undo = board.make_move_in_place(mv);
+        perft(board, count, depth - 1);
+        board.unmake(undo);
     }
 }
 
-pub fn search_infinite(board: &Board, search_moves: Option<Vec<Move>>, halt_receiver: &mpsc::Receiver<HaltCommand>) -> Result<Option<Move>, ()> {
-    let mut moves = search_moves.unwrap_or_else(|| {
+#[allow(clippy::result_unit_err)]
+pub fn search_infinite(
+    board: &Board, options: SearchOptions, tt: Arc<TranspositionTable>, search_moves: Option<Vec<Move>>, history: &[u64],
+    halt_receiver: &mpsc::Receiver<HaltCommand>, info_sender: Option<&mpsc::Sender<UciResponse>>
+) -> Result<SearchResult, ()> {
+    // Keep deepening until a `stop`, sending an `info` line after each completed iteration as `search` does.
+    // The result has the best move found so far, even if the current iteration was cut short.
+    // Only the depth, time and node limits in `options` are ignored; helper threads and `multi_pv` work as in `search`.
+    let moves = search_moves.unwrap_or_else(|| {
         let mut moves = Vec::new();
         gen_legal_moves(board, &mut moves);
         moves
     });
-    let mut best_move = None;
-    let mut depth = 1;
+    let stop = Arc::new(AtomicBool::new(false));
+
+    thread::scope(|scope| {
+        spawn_helpers(scope, board, &moves, &options, &tt, &stop, history);
 
+        let mut ctx = SearchContext::new(tt.clone(), stop.clone(), board, history);
+        ctx.contempt = options.contempt;
+        ctx.eval_params = options.eval_params;
+        let outcome = infinite_main_search(board, moves, options.multi_pv, &mut ctx, halt_receiver, info_sender);
+
+        // Helpers keep going until told to stop, however the main search ended
+        stop.store(true, Ordering::Relaxed);
+        outcome.map(|result| SearchResult { nodes: ctx.nodes, ..result })
+    })
+}
+
+fn infinite_main_search(
+    board: &Board, mut moves: Vec<Move>, multi_pv: usize, ctx: &mut SearchContext,
+    halt_receiver: &mpsc::Receiver<HaltCommand>, info_sender: Option<&mpsc::Sender<UciResponse>>
+) -> Result<SearchResult, ()> {
+    // Every iteration scores all the root moves exactly, so the best `multi_pv` of them are each reported as a line
+    let mut result = SearchResult {
+        best_move: None,
+        score: Score::Centipawns(0),
+        depth: 0,
+        pv: Vec::new(),
+        nodes: 0,
+        lines: Vec::new(),
+    };
+
+    let mut depth = 1;
     loop {
         // Check for a halt command
         if let Ok(halt_cmd) = halt_receiver.try_recv() {
             match halt_cmd {
-                HaltCommand::Stop => return Ok(best_move),
-                HaltCommand::Quit => return Err(())
+                HaltCommand::Stop => break,
+                HaltCommand::Quit => return Err(()),
+                HaltCommand::PonderHit => {}
             }
         }
 
+        if depth > MAX_SEARCH_DEPTH || moves.is_empty() {
+            // Nothing left to search, but the best move still can't be sent until the GUI asks for it
+            loop {
+                match halt_receiver.recv() {
+                    Ok(HaltCommand::Quit) => return Err(()),
+                    Ok(HaltCommand::PonderHit) => continue,
+                    _ => break
+                }
+            }
+            break;
+        }
+
         // Search
-        let result = dfs_search_and_sort(board, &mut moves, &mut best_move, depth, Some(halt_receiver));
-        // Check for a halt command while searching
-        if let Err(halt_command) = result {
-            match halt_command {
-                HaltCommand::Stop => return Ok(best_move),
-                HaltCommand::Quit => return Err(())
+        match dfs_search_and_sort(board, &mut moves, &mut result.best_move, depth, ctx, Some(halt_receiver)) {
+            Ok(score) => {
+                result.complete_iteration(board, depth, score, ctx, multi_pv);
+                send_info(info_sender, &result, ctx.start_time);
+            },
+            Err(HaltCommand::Stop) => break,
+            Err(HaltCommand::Quit) => return Err(()),
+            // There's no clock to start, so just redo the interrupted depth
+            Err(HaltCommand::PonderHit) => {
+                ctx.unwind_path();
+                continue;
             }
         }
 
         depth += 1;
     }
+
+    Ok(result)
+}
+
+fn send_info(info_sender: Option<&mpsc::Sender<UciResponse>>, result: &SearchResult, start_time: Instant) {
+    let (Some(info_sender), Some(_)) = (info_sender, result.best_move) else { return; };
+
+    let elapsed = start_time.elapsed();
+    let nps = (result.nodes as u128 * 1_000_000 / elapsed.as_micros().max(1)) as usize;
+    if result.lines.len() <= 1 {
+        let pv = result.pv.iter().map(Move::uci).collect::<Vec<_>>().join(" ");
+        info_sender.send(UciResponse::Plaintext(format!(
+            "info depth {} score {} nodes {} nps {} time {} pv {}",
+            result.depth, result.score, result.nodes, nps, elapsed.as_millis(), pv
+        ))).expect("stdout error");
+        return;
+    }
+
+    // One line per root move, numbered from the best
+    for (i, (score, pv)) in result.lines.iter().enumerate() {
+        let pv = pv.iter().map(Move::uci).collect::<Vec<_>>().join(" ");
+        info_sender.send(UciResponse::Plaintext(format!(
+            "info depth {} multipv {} score {} nodes {} nps {} time {} pv {}",
+            result.depth, i + 1, score, result.nodes, nps, elapsed.as_millis(), pv
+        ))).expect("stdout error");
+    }
 }
 
+pub fn search_with_info(board: &Board, options: SearchOptions) -> SearchResult {
+    // Search a position on its own, with no game history and no way to stop early
+    let tt = Arc::new(TranspositionTable::new(options.hash_mb));
+    search(board, options, tt, None, &[], None, None).expect("search can only be quit through a halt receiver")
+}
+
+#[allow(clippy::result_unit_err)]
 pub fn search(
-    board: &Board, options: SearchOptions, search_moves: Option<Vec<Move>>, halt_receiver: Option<&mpsc::Receiver<HaltCommand>>
-) -> Result<Option<Move>, ()> {
+    board: &Board, options: SearchOptions, tt: Arc<TranspositionTable>, search_moves: Option<Vec<Move>>, history: &[u64],
+    halt_receiver: Option<&mpsc::Receiver<HaltCommand>>, info_sender: Option<&mpsc::Sender<UciResponse>>
+) -> Result<SearchResult, ()> {
     // Search for the best move in a position using [iterative deepening](https://www.chessprogramming.org/Iterative_Deepening)
-    // If `halt_receiver` is `Some(rx)`, the search can end early if a `HaltCommand` is sent to the receiver. 
+    // `tt` can be kept between searches, so later searches in a game start from what earlier ones found.
+    // `history` holds the hashes of the game's earlier positions, for repetition detection.
+    // If `halt_receiver` is `Some(rx)`, the search can end early if a `HaltCommand` is sent to the receiver.
+    // If `info_sender` is `Some(tx)`, an `info` line is sent after each completed iteration.
+    // With more than one thread, helper threads search the same position and share the transposition table
+    // ([Lazy SMP](https://www.chessprogramming.org/Lazy_SMP)); only the calling thread reports info and picks the move.
     let start_time = Instant::now();
 
-    let SearchOptions { max_depth, time, nodes } = options;
-
-    let mut moves = search_moves.unwrap_or_else(|| {
+    let moves = search_moves.unwrap_or_else(|| {
         let mut moves = Vec::new();
         gen_legal_moves(board, &mut moves);
         moves
     });
 
-    let mut best_move: Option<Move> = None;
+    if moves.is_empty() {
+        // Nothing to search, but the score still tells being checkmated (`mate 0`) apart from stalemate
+        let score = if board.is_check() { Score::Mate(0) } else { Score::Centipawns(DRAW_SCORE) };
+        if let Some(info_sender) = info_sender {
+            info_sender.send(UciResponse::Plaintext(format!("info depth 0 score {}", score))).expect("stdout error");
+        }
+        return Ok(SearchResult { best_move: None, score, depth: 0, pv: Vec::new(), nodes: 0, lines: Vec::new() });
+    }
+
+    let stop = Arc::new(AtomicBool::new(false));
+
+    thread::scope(|scope| {
+        spawn_helpers(scope, board, &moves, &options, &tt, &stop, history);
+
+        let mut ctx = SearchContext::new(tt.clone(), stop.clone(), board, history);
+        ctx.contempt = options.contempt;
+        ctx.eval_params = options.eval_params;
+        ctx.start_time = start_time;
+        let mut result = SearchResult {
+            best_move: None,
+            score: Score::Centipawns(0),
+            depth: 0,
+            pv: Vec::new(),
+            nodes: 0,
+            lines: Vec::new(),
+        };
+        let outcome = main_search(board, options, moves, &mut ctx, &mut result, halt_receiver, info_sender);
+        if options.skill < MAX_SKILL {
+            weaken_move_choice(&mut result, &ctx.root_scores, options.skill, options.seed);
+        }
+
+        // Helpers keep going until told to stop, however the main search ended
+        stop.store(true, Ordering::Relaxed);
+        outcome.map(|()| SearchResult { nodes: ctx.nodes, ..result })
+    })
+}
 
-    for depth in 1..max_depth {
+fn weaken_move_choice(result: &mut SearchResult, root_scores: &[(Move, isize)], skill: u8, seed: u64) {
+    // Replace the best move with a random one scoring within a margin of it, which widens as the skill goes down.
+    // Mates are never given up for a non-mating move, since no margin is that wide
+    let Some(&(_, best_score)) = root_scores.first() else { return; };
+    let margin = (MAX_SKILL - skill) as isize * SKILL_MARGIN_STEP;
+    let candidates = root_scores.iter().take_while(|&&(_, score)| score >= best_score - margin).collect::<Vec<_>>();
+
+    let &(mv, score) = candidates[(PRNG::new(seed as u128).next() % candidates.len() as u64) as usize];
+    if result.best_move != Some(mv) {
+        result.best_move = Some(mv);
+        result.score = Score::from_internal(score);
+        result.pv = vec![mv];
+    }
+}
+
+fn spawn_helpers<'scope>(
+    scope: &'scope thread::Scope<'scope, '_>, board: &'scope Board, moves: &[Move], options: &SearchOptions,
+    tt: &Arc<TranspositionTable>, stop: &Arc<AtomicBool>, history: &[u64]
+) {
+    // Start `options.threads - 1` helper threads, which run until `stop` is set
+    for id in 1..options.threads {
+        let mut ctx = SearchContext::new(tt.clone(), stop.clone(), board, history);
+        ctx.contempt = options.contempt;
+        ctx.eval_params = options.eval_params;
+        let moves = moves.to_vec();
+        scope.spawn(move || helper_search(board, moves, id, &mut ctx));
+    }
+}
+
+fn helper_search(board: &Board, mut moves: Vec<Move>, id: usize, ctx: &mut SearchContext) {
+    // A Lazy SMP helper thread, whose only output is what it leaves in the transposition table.
+    // Starting at a different move and depth from the other threads spreads out the positions they search.
+    if !moves.is_empty() {
+        let first = id % moves.len();
+        moves.rotate_left(first);
+    }
+
+    let mut best_move = None;
+    for depth in (1 + id % 2)..=MAX_SEARCH_DEPTH {
+        if dfs_search_and_sort(board, &mut moves, &mut best_move, depth, ctx, None).is_err() {
+            return;
+        }
+    }
+}
+
+fn main_search(
+    board: &Board, options: SearchOptions, mut moves: Vec<Move>, ctx: &mut SearchContext, result: &mut SearchResult,
+    halt_receiver: Option<&mpsc::Receiver<HaltCommand>>, info_sender: Option<&mpsc::Sender<UciResponse>>
+) -> Result<(), ()> {
+    // Fills in `result` as iterations complete; `result.best_move` is updated as soon as a better move is found.
+    // While [pondering](https://www.chessprogramming.org/Pondering), the depth, time and node limits don't apply
+    // until a `ponderhit` arrives, which starts the clock.
+    let SearchOptions { max_depth, time, nodes, ponder, skill, multi_pv, .. } = options;
+    let mut pondering = ponder && halt_receiver.is_some();
+
+    let mut depth = 1;
+    loop {
         // Check for a halt command
         if let Some(halt_receiver) = halt_receiver {
             if let Ok(halt_cmd) = halt_receiver.try_recv() {
                 match halt_cmd {
-                    HaltCommand::Stop => return Ok(best_move),
-                    HaltCommand::Quit => return Err(())
+                    HaltCommand::Stop => return Ok(()),
+                    HaltCommand::Quit => return Err(()),
+                    HaltCommand::PonderHit => {
+                        pondering = false;
+                        ctx.start_time = Instant::now();
+                    }
                 }
             }
         }
 
+        if pondering {
+            if depth > MAX_SEARCH_DEPTH {
+                // Out of depths, but the move can't be played until the opponent's move is known
+                return wait_for_ponder_end(halt_receiver);
+            }
+        }
         // Check if we have time to do a search at this depth
-        if time.saturating_sub(start_time.elapsed().as_millis() as usize) < next_iter_time_guess(depth) {
-            return Ok(best_move);
+        else if depth > max_depth || time.saturating_sub(ctx.start_time.elapsed().as_millis() as usize) < next_iter_time_guess(depth) {
+            return Ok(());
         }
 
-        // Search
-        let result = dfs_search_and_sort(board, &mut moves, &mut best_move, depth, halt_receiver);
+        // The node and time limits only apply once the first iteration has produced a move
+        if !pondering && depth > 1 {
+            ctx.node_limit = nodes;
+            ctx.time_limit = Some(time);
+        }
+
+        // Search; below full skill or with several lines, every iteration scores all the root moves exactly
+        let is_final = !pondering && depth == max_depth && skill == MAX_SKILL && multi_pv == 1;
+        let iteration = if is_final {
+            dfs_search_final(board, &mut moves, &mut result.best_move, depth, ctx, halt_receiver)
+        } else {
+            dfs_search_and_sort(board, &mut moves, &mut result.best_move, depth, ctx, halt_receiver)
+        };
         // Check for a halt command while searching
-        if let Err(halt_command) = result {
-            match halt_command {
-                HaltCommand::Stop => return Ok(best_move),
-                HaltCommand::Quit => return Err(())
+        match iteration {
+            Ok(score) => {
+                result.complete_iteration(board, depth, score, ctx, multi_pv);
+                send_info(info_sender, result, ctx.start_time);
+            },
+            Err(HaltCommand::Stop) => return Ok(()),
+            Err(HaltCommand::Quit) => return Err(()),
+            // Redo this depth on the clock; the transposition table keeps most of the interrupted work
+            Err(HaltCommand::PonderHit) => {
+                pondering = false;
+                ctx.start_time = Instant::now();
+                ctx.unwind_path();
+                continue;
             }
         }
+
+        if is_final {
+            return Ok(());
+        }
+        depth += 1;
     }
+}
 
-    if time.saturating_sub(start_time.elapsed().as_millis() as usize) < next_iter_time_guess(max_depth) {
-        return Ok(best_move);
+fn wait_for_ponder_end(halt_receiver: Option<&mpsc::Receiver<HaltCommand>>) -> Result<(), ()> {
+    // Block until the ponder search is stopped or its move is played
+    let Some(halt_receiver) = halt_receiver else { return Ok(()); };
+    match halt_receiver.recv() {
+        Ok(HaltCommand::Quit) => Err(()),
+        _ => Ok(())
     }
+}
 
-    // Check for a halt command
-    if let Some(halt_receiver) = halt_receiver {
-        if let Ok(halt_cmd) = halt_receiver.try_recv() {
-            match halt_cmd {
-                HaltCommand::Stop => return Ok(best_move),
-                HaltCommand::Quit => return Err(())
+#[allow(clippy::result_unit_err)]
+pub fn search_mate(
+    board: &Board, max_moves: usize, search_moves: Option<Vec<Move>>, halt_receiver: Option<&mpsc::Receiver<HaltCommand>>,
+    info_sender: Option<&mpsc::Sender<UciResponse>>
+) -> Result<Option<Move>, ()> {
+    // Look for a forced mate in at most `max_moves` moves, trying shorter mates first so the fastest one is found.
+    // Returns `Ok(None)` if there isn't one.
+    let start_time = Instant::now();
+
+    let mut moves = search_moves.unwrap_or_else(|| {
+        let mut moves = Vec::new();
+        gen_legal_moves(board, &mut moves);
+        moves
+    });
+    // Checks are the most likely mating moves
+    moves.sort_by_key(|&mv| !make_move(board, mv).is_check());
+
+    let mut nodes = 0;
+    for mate_in in 1..=max_moves {
+        for &mv in &moves {
+            match is_forced_mate(&make_move(board, mv), 2 * mate_in - 2, &mut nodes, halt_receiver) {
+                Ok(true) => {
+                    if let Some(info_sender) = info_sender {
+                        let elapsed = start_time.elapsed();
+                        let nps = (nodes as u128 * 1_000_000 / elapsed.as_micros().max(1)) as usize;
+                        info_sender.send(UciResponse::Plaintext(format!(
+                            "info depth {} score mate {} nodes {} nps {} time {} pv {}",
+                            2 * mate_in - 1, mate_in, nodes, nps, elapsed.as_millis(), mv.uci()
+                        ))).expect("stdout error");
+                    }
+                    return Ok(Some(mv));
+                },
+                Ok(false) => {},
+                Err(HaltCommand::Stop | HaltCommand::PonderHit) => return Ok(None),
+                Err(HaltCommand::Quit) => return Err(())
             }
         }
     }
 
-    // Final search
-    let result = dfs_search_final(board, &mut moves, &mut best_move, max_depth, halt_receiver);
-    // Check for a halt command while searching
-    if let Err(halt_command) = result {
-        match halt_command {
-            HaltCommand::Stop => return Ok(best_move),
-            HaltCommand::Quit => return Err(())
+    Ok(None)
+}
+
+fn is_forced_mate(
+    board: &Board, plies: usize, nodes: &mut usize, halt_receiver: Option<&mpsc::Receiver<HaltCommand>>
+) -> Result<bool, HaltCommand> {
+    // Whether the side to move (the defender) gets mated within `plies` plies, whatever it plays
+    *nodes += 1;
+
+    let mut defences = Vec::new();
+    gen_legal_moves(board, &mut defences);
+    if defences.is_empty() {
+        return Ok(board.is_check());
+    }
+    if plies == 0 {
+        return Ok(false);
+    }
+
+    for defence in defences {
+        // Check for a halt command
+        if let Some(halt_receiver) = halt_receiver {
+            if let Ok(halt_command) = halt_receiver.try_recv() { return Err(halt_command); }
+        }
+
+        let board = make_move(board, defence);
+        let mut attacks = Vec::new();
+        gen_legal_moves(&board, &mut attacks);
+
+        let mut mated = false;
+        for attack in attacks {
+            if is_forced_mate(&make_move(&board, attack), plies - 2, nodes, halt_receiver)? {
+                mated = true;
+                break;
+            }
+        }
+        if !mated {
+            return Ok(false);
         }
     }
 
-    Ok(best_move)
+    Ok(true)
 }
 
 fn dfs_search_and_sort(
-    board: &Board, moves: &mut Vec<Move>, best_move: &mut Option<Move>, depth: usize, halt_receiver: Option<&mpsc::Receiver<HaltCommand>>
-) -> Result<(), HaltCommand> {
+    board: &Board, moves: &mut [Move], best_move: &mut Option<Move>, depth: usize, ctx: &mut SearchContext,
+    halt_receiver: Option<&mpsc::Receiver<HaltCommand>>
+) -> Result<isize, HaltCommand> {
     // Run depth-first search with a max depth of `depth` and sort `moves` from worst to best.
     // The function also updates `best_move` as soon as a better move is discovered; combined with move-sorting from previous iterations,
     // this means that `best_move` will have a reasonable move at any sufficiently late point in the search function.
     // Alpha-beta pruning isn't used when iterating over `moves` because in order to sort the moves accurately, each move's score must be fully calculated.
     let mut best_score = -isize::MAX;
+    let mut board = *board;
+    ctx.root_depth = depth;
 
     let mut scores = HashMap::new();
     for mv in moves.iter().cloned() {
@@ -234,13 +926,15 @@ fn dfs_search_and_sort(
             if let Ok(halt_command) = halt_receiver.try_recv() { return Err(halt_command); }
         }
 
+        let undo = board.make_move_in_place(mv);
         let score = -negamax(
-            &make_move(board, mv), depth - 1, -isize::MAX, isize::MAX, halt_receiver
+            &mut board, depth - 1, 1, -isize::MAX, isize::MAX, ctx, halt_receiver
         )?;
+        board.unmake(undo);
 
         if score > best_score {
             best_score = score;
-            *best_move = Some(mv.clone());
+            *best_move = Some(mv);
         }
 
         scores.insert(mv, score);
@@ -252,16 +946,21 @@ fn dfs_search_and_sort(
     }
 
     moves.sort_by_key(|mv| -scores.get(mv).unwrap());
+    ctx.root_scores.clear();
+    ctx.root_scores.extend(moves.iter().map(|mv| (*mv, scores[mv])));
 
-    Ok(())
+    Ok(best_score)
 }
 
 fn dfs_search_final(
-    board: &Board, moves: &mut Vec<Move>, best_move: &mut Option<Move>, max_depth: usize, halt_receiver: Option<&mpsc::Receiver<HaltCommand>>
-) -> Result<(), HaltCommand> {
+    board: &Board, moves: &mut [Move], best_move: &mut Option<Move>, max_depth: usize, ctx: &mut SearchContext,
+    halt_receiver: Option<&mpsc::Receiver<HaltCommand>>
+) -> Result<isize, HaltCommand> {
     // Run depth-first search with a max depth of `depth`, utilizing alpha-beta pruning on the provided moves to maximize speed.
     let mut best_score = -isize::MAX;
     let mut alpha = -isize::MAX;
+    let mut board = *board;
+    ctx.root_depth = max_depth;
 
     for &mut mv in moves {
         // Check for a halt command
@@ -269,89 +968,573 @@ fn dfs_search_final(
             if let Ok(halt_command) = halt_receiver.try_recv() { return Err(halt_command); }
         }
 
+        let undo = board.make_move_in_place(mv);
         let score = -negamax(
-            &make_move(board, mv), max_depth - 1, -isize::MAX, -alpha, halt_receiver
+            &mut board, max_depth - 1, 1, -isize::MAX, -alpha, ctx, halt_receiver
         )?;
+        board.unmake(undo);
 
         if score > best_score {
             best_score = score;
-            *best_move = Some(mv.clone());
+            *best_move = Some(mv);
 
             if score > alpha {
                 alpha = score;
-                if score == isize::MAX {
-                    // checkmate! dubious actually...
-                    return Ok(());
+                if score == MATE - 1 {
+                    // Mate in one can't be improved on
+                    return Ok(score);
                 }
             }
         }
     }
-    Ok(())
+    Ok(best_score)
 }
 
 fn negamax(
-    board: &Board, depth: usize, mut alpha: isize, beta: isize, halt_receiver: Option<&mpsc::Receiver<HaltCommand>>
+    board: &mut Board, mut depth: usize, ply: usize, mut alpha: isize, beta: isize, ctx: &mut SearchContext,
+    halt_receiver: Option<&mpsc::Receiver<HaltCommand>>
 ) -> Result<isize, HaltCommand> {
     // Recursively find the a position's score using [negamax](https://www.chessprogramming.org/Negamax)
+    let hash = board.get_hash();
+
+    // Any repetition is treated as a draw, since the side that's better off could avoid it
+    if ctx.is_repetition(hash, board.get_halfmoves()) {
+        return Ok(ctx.draw_score(ply));
+    }
+
+    // [Check extension](https://www.chessprogramming.org/Check_Extensions): search replies to checks a ply deeper,
+    // which also keeps positions in check out of quiescence search, where only captures would be tried
+    let in_check = board.is_check();
+    if in_check && (ply + depth).saturating_sub(ctx.root_depth) < MAX_CHECK_EXTENSIONS {
+        depth += 1;
+    }
+
     if depth == 0 {
-        return Ok(relative_score(board));
+        return quiescence(board, alpha, beta, ctx, halt_receiver);
     }
 
-    let mut moves = Vec::new();
+    ctx.count_node()?;
+
+    // Probe the transposition table
+    let alpha_orig = alpha;
+    let tt_entry = ctx.tt.probe(hash);
+    if let Some(entry) = tt_entry {
+        if entry.depth >= depth {
+            let score = score_from_tt(entry.score, ply);
+            match entry.node_type {
+                NodeType::Exact => return Ok(score),
+                NodeType::LowerBound if score >= beta => return Ok(score),
+                NodeType::UpperBound if score <= alpha => return Ok(score),
+                _ => {}
+            }
+        }
+    }
+
+    // [Null move pruning](https://www.chessprogramming.org/Null_Move_Pruning): if passing still gets a beta cutoff
+    // from a shallower search, a real move almost certainly would too. Passing would be illegal in check, and in
+    // zugzwang, which is common once only pawns are left, it's better than any real move, so those are skipped
+    if depth >= NULL_MOVE_MIN_DEPTH && !ctx.in_null_move && !is_mate_score(beta) && !in_check
+    && has_non_pawn_material(board, board.get_side_to_move()) && evaluate_with(board, &ctx.eval_params) >= beta {
+        ctx.in_null_move = true;
+        ctx.path.push(hash);
+        let null_score = negamax(
+            &mut board.make_null_move(), depth - 1 - NULL_MOVE_REDUCTION, ply + 1, -beta, -beta + 1, ctx, halt_receiver
+        );
+        ctx.path.pop();
+        ctx.in_null_move = false;
+
+        if -null_score? >= beta {
+            return Ok(beta);
+        }
+    }
+
+    let mut moves = ctx.take_move_list();
     gen_legal_moves(board, &mut moves);
-    if moves.len() == 0 {
-        return Ok(if board.is_check() {
-            -isize::MAX
+    if moves.is_empty() {
+        ctx.return_move_list(moves);
+        return Ok(if in_check {
+            -(MATE - ply as isize)
         } else {
-            0
+            ctx.draw_score(ply)
         });
     }
 
+    // Fifty-move rule (checkmate takes precedence, which is handled above)
+    if board.get_halfmoves() >= 100 {
+        ctx.return_move_list(moves);
+        return Ok(ctx.draw_score(ply));
+    }
+
+    // Try the stored best move first, then captures and promotions, then killer moves
+    moves.sort_by_key(|&mv| match score_move_mvvlva(board, mv) {
+        0 => -ctx.killer_score(mv, ply),
+        score => -score
+    });
+    if let Some(tt_move) = tt_entry.and_then(|entry| entry.best_move) {
+        if let Some(idx) = moves.iter().position(|&mv| mv == tt_move) {
+            moves.swap(0, idx);
+        }
+    }
+
     let mut max = -isize::MAX;
-    for mv in moves {
+    let mut best_move = None;
+    ctx.path.push(hash);
+    for &mv in &moves {
         // Check for a halt command
         if let Some(halt_receiver) = halt_receiver {
             if let Ok(halt_command) = halt_receiver.try_recv() { return Err(halt_command); }
         }
 
+        let undo = board.make_move_in_place(mv);
         let score = -negamax(
-            &make_move(board, mv), depth - 1, -beta, -alpha, halt_receiver
+            board, depth - 1, ply + 1, -beta, -alpha, ctx, halt_receiver
         )?;
+        board.unmake(undo);
 
         if score > max {
             max = score;
+            best_move = Some(mv);
             if score > alpha {
                 alpha = score;
                 if alpha >= beta {
+                    // Quiet moves that cause a cutoff are likely to do so in sibling positions too
+                    if score_move_mvvlva(board, mv) == 0 {
+                        ctx.store_killer(mv, ply);
+                    }
                     break;
                 }
             }
         }
     }
+    ctx.path.pop();
+    ctx.return_move_list(moves);
+
+    let node_type = if max <= alpha_orig {
+        NodeType::UpperBound
+    } else if max >= beta {
+        NodeType::LowerBound
+    } else {
+        NodeType::Exact
+    };
+    ctx.tt.store(TtEntry { hash, depth, score: score_to_tt(max, ply), node_type, best_move });
+
     Ok(max)
 }
 
+fn quiescence(
+    board: &mut Board, mut alpha: isize, beta: isize, ctx: &mut SearchContext, halt_receiver: Option<&mpsc::Receiver<HaltCommand>>
+) -> Result<isize, HaltCommand> {
+    // Keep searching captures and promotions until the position is quiet, so that the static evaluation
+    // isn't taken in the middle of an exchange ([quiescence search](https://www.chessprogramming.org/Quiescence_Search))
+    ctx.count_node()?;
+
+    let stand_pat = evaluate_with(board, &ctx.eval_params);
+    if stand_pat >= beta {
+        return Ok(stand_pat);
+    }
+    if stand_pat > alpha {
+        alpha = stand_pat;
+    }
+
+    let mut moves = ctx.take_move_list();
+    gen_captures(board, &mut moves);
+    moves.sort_by_key(|&mv| -score_move_mvvlva(board, mv));
+
+    for &mv in &moves {
+        // Check for a halt command
+        if let Some(halt_receiver) = halt_receiver {
+            if let Ok(halt_command) = halt_receiver.try_recv() { return Err(halt_command); }
+        }
+
+        // Captures that lose material once all recaptures are played out can't raise alpha
+        if see(board, mv) < 0 {
+            continue;
+        }
+
+        let undo = board.make_move_in_place(mv);
+        let score = -quiescence(board, -beta, -alpha, ctx, halt_receiver)?;
+        board.unmake(undo);
+
+        if score >= beta {
+            ctx.return_move_list(moves);
+            return Ok(score);
+        }
+        if score > alpha {
+            alpha = score;
+        }
+    }
+    ctx.return_move_list(moves);
+
+    Ok(alpha)
+}
+
+// Values the king highly enough that an exchange never ends with it being captured
+const SEE_KING_VALUE: isize = 100 * MATERIAL_FACTOR;
+// Longest possible capture sequence on one square: every piece on the board
+const SEE_MAX_DEPTH: usize = 32;
+
+const fn see_value(piece: Piece) -> isize {
+    match piece {
+        Piece::King => SEE_KING_VALUE,
+        piece => MATERIAL_FACTOR * material(piece)
+    }
+}
+
+fn see(board: &Board, mv: Move) -> isize {
+    // Find the material outcome of the exchange on `mv.to` started by `mv`, with each side recapturing
+    // using its least valuable attacker ([static exchange evaluation](https://www.chessprogramming.org/SEE_-_The_Swap_Algorithm))
+    let target = mv.to;
+    let mut occupied = board.blockers();
+    let mut gain = [0; SEE_MAX_DEPTH];
+
+    gain[0] = match mv.move_type {
+        MoveType::EnPassant => {
+            // The captured pawn isn't on the target square
+            occupied ^= Bitboard::from_square(Square::from_coords(target.file(), mv.from.rank()));
+            see_value(Piece::Pawn)
+        },
+        MoveType::Castle(_) => return 0,
+        _ => board.get_piece_at(target).map_or(0, see_value)
+    };
+
+    let mut attacker = board.get_piece_at(mv.from).expect("no piece on move origin");
+    let mut from = Bitboard::from_square(mv.from);
+    let mut side = board.get_side_to_move();
+    let mut depth = 0;
+
+    while depth + 1 < SEE_MAX_DEPTH {
+        occupied ^= from;
+        side = !side;
+
+        // Sliders behind the pieces that have already captured are revealed by recomputing with `occupied`
+        let attackers = attackers_to(board, target, side, occupied) & occupied;
+        let Some(next) = [Piece::Pawn, Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen, Piece::King]
+            .into_iter()
+            .find(|&piece| !(attackers & board.get_piece(piece)).is_empty())
+        else {
+            break;
+        };
+
+        // `side` captures the piece that just captured
+        depth += 1;
+        gain[depth] = see_value(attacker) - gain[depth - 1];
+
+        attacker = next;
+        from = Bitboard::from_square((attackers & board.get_piece(next)).to_square());
+    }
+
+    // Either side can stop capturing whenever continuing would be worse
+    while depth > 0 {
+        gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+        depth -= 1;
+    }
+
+    gain[0]
+}
+
+const MVV_LVA_CAPTURE: isize = 1000;
+const MVV_LVA_PROMOTION: isize = 1000;
+const MVV_LVA_UNDERPROMOTION: isize = -1000;
+
+fn score_move_mvvlva(board: &Board, mv: Move) -> isize {
+    // Order captures by most valuable victim, then least valuable attacker ([MVV-LVA](https://www.chessprogramming.org/MVV-LVA)).
+    // Queen promotions rank alongside capturing a queen. Quiet moves score 0.
+    // Underpromotions are still searched, since they're occasionally the only good move, but after everything else:
+    // the queen promotion to the same square is almost always at least as good
+    let mut score = 0;
+
+    if let Some(piece) = mv.is_promotion() {
+        if piece != Piece::Queen {
+            return MVV_LVA_UNDERPROMOTION + material(piece);
+        }
+        score += MVV_LVA_PROMOTION + 10 * material(piece);
+    }
+
+    if mv.is_capture(board) {
+        let victim = if mv.is_en_passant() { Piece::Pawn } else { board.get_piece_at(mv.to).unwrap() };
+        let attacker = board.get_piece_at(mv.from).expect("no piece on move origin");
+        score += MVV_LVA_CAPTURE + 10 * material(victim) - material(attacker);
+    }
+
+    score
+}
+
 const MATERIAL_FACTOR: isize = 100;
-const PST_FACTOR: isize = 1;
 
-fn relative_score(board: &Board) -> isize {
-    score_side(board, board.get_side_to_move()) - score_side(board, !board.get_side_to_move())
+// Weights of the evaluation terms, in centipawns unless noted, so they can be tuned without recompiling
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EvalParams {
+    // Indexed by `Piece::idx`
+    pub piece_values: [isize; NUM_PIECES],
+    // Multiplies the piece-square table values
+    pub pst_factor: isize,
+    pub doubled_pawn_penalty: isize,
+    pub isolated_pawn_penalty: isize,
+    pub passed_pawn_bonus: isize,
+    // Per square a piece can move to
+    pub knight_mobility: isize,
+    pub bishop_mobility: isize,
+    pub rook_mobility: isize,
+    pub queen_mobility: isize,
+    pub missing_shield_pawn_penalty: isize,
+    pub semi_open_king_file_penalty: isize,
+    pub open_king_file_penalty: isize,
+    // Per square next to the enemy king a piece attacks
+    pub knight_king_attack: isize,
+    pub bishop_king_attack: isize,
+    pub rook_king_attack: isize,
+    pub queen_king_attack: isize,
+}
+
+pub const DEFAULT_EVAL_PARAMS: EvalParams = EvalParams {
+    piece_values: {
+        let mut values = [0; NUM_PIECES];
+        let mut piece_idx = 0;
+        while piece_idx < NUM_PIECES {
+            values[piece_idx] = MATERIAL_FACTOR * material(PIECES[piece_idx]);
+            piece_idx += 1;
+        }
+        values
+    },
+    pst_factor: 1,
+    doubled_pawn_penalty: 15,
+    isolated_pawn_penalty: 15,
+    passed_pawn_bonus: 30,
+    knight_mobility: 4,
+    bishop_mobility: 4,
+    rook_mobility: 2,
+    queen_mobility: 1,
+    missing_shield_pawn_penalty: 20,
+    semi_open_king_file_penalty: 15,
+    open_king_file_penalty: 25,
+    knight_king_attack: 6,
+    bishop_king_attack: 6,
+    rook_king_attack: 8,
+    queen_king_attack: 12,
+};
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        DEFAULT_EVAL_PARAMS
+    }
+}
+
+// Game phase runs from PHASE_MAX with all pieces on the board down to 0 with bare kings
+const PHASE_MAX: isize = 256;
+const PHASE_TOTAL: isize = 24;
+
+pub fn evaluate(board: &Board) -> isize {
+    // Static evaluation in centipawns from the side to move's perspective, without any search
+    evaluate_with(board, &DEFAULT_EVAL_PARAMS)
+}
+
+pub fn evaluate_with(board: &Board, params: &EvalParams) -> isize {
+    // `evaluate` with the given weights.
+    // Interpolate between middlegame and endgame scores based on the game phase ([tapered eval](https://www.chessprogramming.org/Tapered_Eval))
+    let (us_mg, us_eg) = score_side(board, board.get_side_to_move(), params);
+    let (them_mg, them_eg) = score_side(board, !board.get_side_to_move(), params);
+
+    let phase = game_phase(board);
+    ((us_mg - them_mg) * phase + (us_eg - them_eg) * (PHASE_MAX - phase)) / PHASE_MAX
+}
+
+pub fn evaluate_absolute(board: &Board) -> isize {
+    // Like `evaluate`, but positive when White is better, as most GUIs show it
+    match board.get_side_to_move() {
+        Color::White => evaluate(board),
+        Color::Black => -evaluate(board)
+    }
+}
+
+fn game_phase(board: &Board) -> isize {
+    let phase = PIECES.into_iter()
+        .map(|piece| phase_weight(piece) * board.get_piece(piece).count() as isize)
+        .sum::<isize>();
+
+    // Promotions can push the total past the starting material
+    phase.min(PHASE_TOTAL) * PHASE_MAX / PHASE_TOTAL
+}
+
+#[inline]
+fn has_non_pawn_material(board: &Board, color: Color) -> bool {
+    // Pieces other than the king and pawns
+    !(board.get_color(color) & !board.get_piece(Piece::Pawn) & !board.get_piece(Piece::King)).is_empty()
+}
+
+fn score_side(board: &Board, color: Color, params: &EvalParams) -> (isize, isize) {
+    let mut mg = 0;
+    let mut eg = 0;
+
+    for (square, piece) in board.piece_list(color) {
+        let value = params.piece_values[piece.idx()];
+        mg += value;
+        eg += value;
+        mg += params.pst_factor * psts::get_mg(piece, color, square);
+        eg += params.pst_factor * psts::get_eg(piece, color, square);
+    }
+
+    let pawn_structure = score_pawn_structure(board, color, params);
+    mg += pawn_structure;
+    eg += pawn_structure;
+
+    let mobility = score_mobility(board, color, params);
+    mg += mobility;
+    eg += mobility;
+
+    // An exposed king only matters while there's enough material left to attack it
+    mg += score_king_safety(board, color, params);
+
+    (mg, eg)
+}
+
+fn score_mobility(board: &Board, color: Color, params: &EvalParams) -> isize {
+    // Count the squares each minor and major piece can move to ([mobility](https://www.chessprogramming.org/Mobility)).
+    // Kings and pawns are left out, since their moves say more about safety and structure than activity
+    let blockers = board.blockers();
+    let not_own = !board.get_color(color);
+    let mut score = 0;
+
+    for (piece, weight) in [
+        (Piece::Knight, params.knight_mobility),
+        (Piece::Bishop, params.bishop_mobility),
+        (Piece::Rook, params.rook_mobility),
+        (Piece::Queen, params.queen_mobility)
+    ] {
+        for square in board.pieces_of(color, piece) {
+            score += weight * (gen_piece_attacks(piece, color, square, blockers) & not_own).count() as isize;
+        }
+    }
+
+    score
+}
+
+fn score_king_safety(board: &Board, color: Color, params: &EvalParams) -> isize {
+    // Penalize missing shield pawns, open files near the king, and enemy pieces attacking the squares around it
+    // ([king safety](https://www.chessprogramming.org/King_Safety))
+    let Some(king) = board.pieces_of(color, Piece::King).next() else { return 0; };
+    let pawns = board.pieces_of(color, Piece::Pawn);
+    let enemy_pawns = board.pieces_of(!color, Piece::Pawn);
+    let mut score = 0;
+
+    for file_square in [king.left(), Some(king), king.right()].into_iter().flatten() {
+        let one_ahead = file_square.forward(color);
+        let two_ahead = one_ahead.and_then(|square| square.forward(color));
+        if ![one_ahead, two_ahead].into_iter().flatten().any(|square| pawns.contains(square)) {
+            score -= params.missing_shield_pawn_penalty;
+        }
+
+        let file_mask = FILE_MASKS[file_square.file() as usize];
+        if (pawns & file_mask).is_empty() {
+            score -= if (enemy_pawns & file_mask).is_empty() {
+                params.open_king_file_penalty
+            } else {
+                params.semi_open_king_file_penalty
+            };
+        }
+    }
+
+    let blockers = board.blockers();
+    let king_zone = gen_piece_attacks(Piece::King, color, king, blockers) | Bitboard::from_square(king);
+    for (piece, weight) in [
+        (Piece::Knight, params.knight_king_attack),
+        (Piece::Bishop, params.bishop_king_attack),
+        (Piece::Rook, params.rook_king_attack),
+        (Piece::Queen, params.queen_king_attack)
+    ] {
+        for square in board.pieces_of(!color, piece) {
+            score -= weight * (gen_piece_attacks(piece, !color, square, blockers) & king_zone).count() as isize;
+        }
+    }
+
+    score
 }
 
-fn score_side(board: &Board, color: Color) -> isize {
+fn score_pawn_structure(board: &Board, color: Color, params: &EvalParams) -> isize {
+    // Penalize doubled and isolated pawns and reward passed pawns ([pawn structure](https://www.chessprogramming.org/Pawn_Structure))
+    let pawns = board.pieces_of(color, Piece::Pawn);
+    let enemy_pawns = board.pieces_of(!color, Piece::Pawn);
     let mut score = 0;
 
-    for piece in PIECES {
-        let material = material(piece);
-        for square in board.get_piece(piece) & board.get_color(color) {
-            score += MATERIAL_FACTOR * material;
-            score += PST_FACTOR * psts::get_mg(piece, color, square);
+    for file_mask in FILE_MASKS {
+        let count = (pawns & file_mask).count() as isize;
+        if count > 1 {
+            score -= params.doubled_pawn_penalty * (count - 1);
+        }
+    }
+
+    for square in pawns {
+        if (pawns & ADJACENT_FILE_MASKS[square.file() as usize]).is_empty() {
+            score -= params.isolated_pawn_penalty;
+        }
+        if (enemy_pawns & PASSED_PAWN_MASKS[color.idx()][square.idx()]).is_empty() {
+            score += params.passed_pawn_bonus;
         }
     }
 
     score
 }
 
+const FILE_MASKS: [Bitboard; NUM_FILES] = {
+    let mut masks = [Bitboard::EMPTY; NUM_FILES];
+
+    let mut file = 0;
+    while file < NUM_FILES {
+        masks[file] = Bitboard(0x0101010101010101 << file);
+        file += 1;
+    }
+
+    masks
+};
+
+const ADJACENT_FILE_MASKS: [Bitboard; NUM_FILES] = {
+    let mut masks = [Bitboard::EMPTY; NUM_FILES];
+
+    let mut file = 0;
+    while file < NUM_FILES {
+        if file > 0 {
+            masks[file].0 |= FILE_MASKS[file - 1].0;
+        }
+        if file < NUM_FILES - 1 {
+            masks[file].0 |= FILE_MASKS[file + 1].0;
+        }
+        file += 1;
+    }
+
+    masks
+};
+
+// Squares on the same and adjacent files in front of a pawn, which must be free of enemy pawns for it to be passed
+const PASSED_PAWN_MASKS: [[Bitboard; NUM_SQUARES]; 2] = {
+    let mut masks = [[Bitboard::EMPTY; NUM_SQUARES]; 2];
+
+    let mut square_idx = 0;
+    while square_idx < NUM_SQUARES {
+        let square = Square::from_idx(square_idx);
+        let file = square.file() as usize;
+        let rank = square.rank() as usize;
+        let files = FILE_MASKS[file].0 | ADJACENT_FILE_MASKS[file].0;
+
+        let ahead_white = if rank == 7 { 0 } else { u64::MAX << (8 * (rank + 1)) };
+        let ahead_black = (1 << (8 * rank)) - 1;
+
+        masks[Color::White.idx()][square_idx] = Bitboard(files & ahead_white);
+        masks[Color::Black.idx()][square_idx] = Bitboard(files & ahead_black);
+        square_idx += 1;
+    }
+
+    masks
+};
+
+const fn phase_weight(piece: Piece) -> isize {
+    match piece {
+        Piece::Rook => 2,
+        Piece::Knight => 1,
+        Piece::Bishop => 1,
+        Piece::Queen => 4,
+        Piece::King | Piece::Pawn => 0
+    }
+}
+
 const fn material(piece: Piece) -> isize {
     match piece {
         Piece::Rook => 5,
@@ -361,4 +1544,230 @@ const fn material(piece: Piece) -> isize {
         Piece::Queen => 9,
         Piece::Pawn => 1
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::{apply_uci_moves, init_magic_tables, START_POS_FEN};
+
+    // Positions from https://www.chessprogramming.org/Perft_Results
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    const POSITION_3: &str = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
+    const POSITION_4: &str = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+    const POSITION_5: &str = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8";
+
+    fn perft_count(fen: &str, depth: usize) -> usize {
+        init_magic_tables();
+        let mut count = 0;
+        perft(&mut Board::new(fen).unwrap(), &mut count, depth);
+        count
+    }
+
+    #[test]
+    fn perft_start_pos() {
+        assert_eq!(perft_count(START_POS_FEN, 4), 197281);
+    }
+
+    #[test]
+    fn perft_kiwipete() {
+        assert_eq!(perft_count(KIWIPETE, 3), 97862);
+    }
+
+    #[test]
+    fn perft_position_3() {
+        assert_eq!(perft_count(POSITION_3, 5), 674624);
+    }
+
+    #[test]
+    fn perft_position_4() {
+        assert_eq!(perft_count(POSITION_4, 4), 422333);
+    }
+
+    #[test]
+    fn perft_position_5() {
+        assert_eq!(perft_count(POSITION_5, 3), 62379);
+    }
+
+    #[test]
+    fn perft_hashed_matches_perft() {
+        init_magic_tables();
+        for (fen, depth) in [(START_POS_FEN, 4), (KIWIPETE, 3), (POSITION_3, 5), (POSITION_4, 4), (POSITION_5, 3)] {
+            assert_eq!(perft_hashed(&Board::new(fen).unwrap(), depth), perft_count(fen, depth), "{}", fen);
+        }
+    }
+
+    fn go_options() -> UciGoOptions {
+        UciGoOptions {
+            search_moves: None,
+            ponder: false,
+            wtime: None,
+            btime: None,
+            winc: None,
+            binc: None,
+            moves_to_go: None,
+            depth: None,
+            nodes: None,
+            mate: None,
+            move_time: None,
+            infinite: false,
+            perft: None,
+        }
+    }
+
+    #[test]
+    fn decide_options_unlimited_time() {
+        init_magic_tables();
+        let board = Board::default();
+        let config = EngineConfig::default();
+
+        let options = decide_options(&board, &UciGoOptions { move_time: Some(usize::MAX), ..go_options() }, &config);
+        assert_eq!((options.time, options.max_depth), (usize::MAX, MAX_DEPTH));
+
+        let options = decide_options(&board, &go_options(), &config);
+        assert_eq!((options.time, options.max_depth), (MAX_TIME, MAX_DEPTH));
+
+        let options = decide_options(&board, &UciGoOptions { depth: Some(3), ..go_options() }, &config);
+        assert_eq!(options.max_depth, 3);
+
+        // None of these should overflow
+        let options = decide_options(&board, &UciGoOptions {
+            wtime: Some(usize::MAX), winc: Some(usize::MAX), moves_to_go: Some(usize::MAX), ..go_options()
+        }, &config);
+        assert_eq!(options.max_depth, MAX_DEPTH);
+    }
+
+    #[test]
+    fn decide_options_tiny_time() {
+        init_magic_tables();
+        let board = Board::default();
+        let config = EngineConfig::default();
+
+        // There's always time for depth 1, so there's a move to play
+        for go in [UciGoOptions { move_time: Some(0), ..go_options() }, UciGoOptions { wtime: Some(0), ..go_options() }] {
+            let options = decide_options(&board, &go, &config);
+            assert_eq!((options.time, options.max_depth), (0, 1));
+        }
+    }
+
+    #[test]
+    fn decide_options_clock_time() {
+        init_magic_tables();
+        let board = Board::default();
+        let config = EngineConfig::default();
+
+        // A minute left is split over 20 more moves, which leaves time for depth 5
+        let options = decide_options(&board, &UciGoOptions { wtime: Some(60_000), ..go_options() }, &config);
+        assert_eq!((options.time, options.max_depth), (3000, 5));
+
+        // Black's clock is used when it's black to move
+        let board = apply_uci_moves(&board, &["e2e4"]).unwrap();
+        let options = decide_options(&board, &UciGoOptions { wtime: Some(60_000), btime: Some(2000), ..go_options() }, &config);
+        assert_eq!((options.time, options.max_depth), (100, 3));
+    }
+
+    #[test]
+    fn infinite_search_stops_helper_threads() {
+        init_magic_tables();
+        let board = Board::new(KIWIPETE).unwrap();
+        let options = SearchOptions { threads: 3, ..decide_options(&board, &UciGoOptions { infinite: true, ..go_options() }, &EngineConfig::default()) };
+        let tt = Arc::new(TranspositionTable::new(1));
+        let (halt_sender, halt_receiver) = mpsc::channel();
+
+        // Returning at all means the helpers saw the stop, since the thread scope waits for them
+        thread::spawn(move || {
+            thread::sleep(std::time::Duration::from_millis(200));
+            halt_sender.send(HaltCommand::Stop).unwrap();
+        });
+        let result = search_infinite(&board, options, tt, None, &[], &halt_receiver, None).unwrap();
+        assert!(result.best_move.is_some());
+    }
+
+    #[test]
+    fn infinite_search_reports_multi_pv() {
+        init_magic_tables();
+        let board = Board::default();
+        let options = SearchOptions { multi_pv: 3, ..decide_options(&board, &UciGoOptions { infinite: true, ..go_options() }, &EngineConfig::default()) };
+        let tt = Arc::new(TranspositionTable::new(1));
+        let (halt_sender, halt_receiver) = mpsc::channel();
+        let (info_sender, info_receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            thread::sleep(std::time::Duration::from_millis(200));
+            halt_sender.send(HaltCommand::Stop).unwrap();
+        });
+        let result = search_infinite(&board, options, tt, None, &[], &halt_receiver, Some(&info_sender)).unwrap();
+        assert_eq!(result.lines.len(), 3);
+
+        // Each completed depth sends one line per root move, numbered from the best
+        let lines = info_receiver.try_iter().map(|response| match response {
+            UciResponse::Plaintext(text) => text,
+            _ => panic!("expected info lines")
+        }).collect::<Vec<_>>();
+        assert!(!lines.is_empty());
+        for (i, line) in lines.iter().enumerate() {
+            assert!(line.contains(&format!(" multipv {} ", i % 3 + 1)), "{}", line);
+        }
+    }
+
+    #[test]
+    fn deepest_mates_are_mate_scores() {
+        // A mate at the deepest ply the check extensions can reach still scores as a mate
+        let deepest_ply = MAX_SEARCH_DEPTH + MAX_CHECK_EXTENSIONS;
+        assert!(deepest_ply < MAX_PLY);
+        assert!(is_mate_score(MATE - deepest_ply as isize));
+        assert!(is_mate_score(-(MATE - deepest_ply as isize)));
+        assert_eq!(Score::from_internal(MATE - deepest_ply as isize), Score::Mate(deepest_ply.div_ceil(2) as isize));
+    }
+
+    #[test]
+    fn halted_search_path_unwinds_to_root() {
+        init_magic_tables();
+        let board = Board::new(KIWIPETE).unwrap();
+        let history = [1, 2, 3];
+        let tt = Arc::new(TranspositionTable::new(1));
+        let mut ctx = SearchContext::new(tt, Arc::new(AtomicBool::new(false)), &board, &history);
+
+        // Run out of nodes partway down a branch
+        ctx.node_limit = Some(100);
+        let mut moves = Vec::new();
+        gen_legal_moves(&board, &mut moves);
+        let iteration = dfs_search_and_sort(&board, &mut moves, &mut None, 4, &mut ctx, None);
+        assert!(matches!(iteration, Err(HaltCommand::Stop)));
+        assert!(ctx.path.len() > history.len() + 1);
+
+        ctx.unwind_path();
+        assert_eq!(ctx.path, [1, 2, 3, board.get_hash()]);
+    }
+
+    #[test]
+    fn evaluation_is_color_symmetric() {
+        init_magic_tables();
+        for fen in [
+            KIWIPETE,
+            POSITION_3,
+            POSITION_4,
+            POSITION_5,
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "rnbqkb1r/pp3ppp/4pn2/2pp4/3P4/2N1PN2/PPP2PPP/R1BQKB1R w Kq c6 0 5",
+            "r1bq1rk1/ppp2ppp/2np1n2/2b1p3/2B1P3/2NP1N2/PPP2PPP/R1BQ1RK1 b - - 3 7",
+            "8/5k2/3p4/1p1Pp2p/pP2Pp1P/P4P1K/8/8 b - - 99 50",
+            "6k1/5p2/6p1/8/7p/8/6PP/6K1 b - - 0 1",
+        ] {
+            let board = Board::new(fen).unwrap();
+            let mirrored = board.mirror();
+            assert_ne!(board, mirrored, "{fen}");
+            assert_eq!(mirrored.mirror(), board, "{fen}");
+            assert_eq!(evaluate(&board), evaluate(&mirrored), "{fen}");
+        }
+    }
+
+    #[test]
+    #[ignore = "slow; run with --release -- --ignored"]
+    fn perft_deep() {
+        assert_eq!(perft_count(START_POS_FEN, 5), 4865609);
+        assert_eq!(perft_count(KIWIPETE, 5), 193690690);
+        assert_eq!(perft_count(POSITION_3, 6), 11030083);
+        assert_eq!(perft_count(POSITION_4, 5), 15833292);
+        assert_eq!(perft_count(POSITION_5, 4), 2103487);
+    }
+}
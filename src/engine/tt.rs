@@ -0,0 +1,60 @@
+// https://www.chessprogramming.org/Transposition_Table
+
+use crate::chess::Move;
+
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NodeType {
+    Exact,
+    LowerBound,
+    UpperBound
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TtEntry {
+    pub hash: u64,
+    pub depth: usize,
+    pub score: isize,
+    pub node_type: NodeType,
+    pub best_move: Option<Move>
+}
+
+// Shared between search threads, with a lock per entry so threads rarely wait on each other
+pub struct TranspositionTable {
+    entries: Vec<Mutex<Option<TtEntry>>>
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> Self {
+        let len = (size_mb * 1024 * 1024 / std::mem::size_of::<Mutex<Option<TtEntry>>>()).max(1);
+        Self { entries: (0..len).map(|_| Mutex::new(None)).collect() }
+    }
+
+    pub fn clear(&self) {
+        for entry in &self.entries {
+            *entry.lock().expect("transposition table lock poisoned") = None;
+        }
+    }
+
+    #[inline]
+    fn idx(&self, hash: u64) -> usize {
+        (hash % self.entries.len() as u64) as usize
+    }
+
+    #[inline]
+    pub fn probe(&self, hash: u64) -> Option<TtEntry> {
+        self.entries[self.idx(hash)].lock().expect("transposition table lock poisoned")
+            .filter(|entry| entry.hash == hash)
+    }
+
+    #[inline]
+    pub fn store(&self, entry: TtEntry) {
+        // Depth-preferred replacement, except that entries for other positions are always overwritten
+        let mut slot = self.entries[self.idx(entry.hash)].lock().expect("transposition table lock poisoned");
+        match *slot {
+            Some(old) if old.hash == entry.hash && old.depth > entry.depth => {},
+            _ => *slot = Some(entry)
+        }
+    }
+}
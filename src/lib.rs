@@ -0,0 +1,10 @@
+pub mod book;
+pub mod chess;
+pub mod engine;
+mod prng;
+pub mod uci;
+mod zobrist;
+
+use crate::zobrist::ZobristHasher;
+
+pub static ZOBRIST_HASHER: ZobristHasher = ZobristHasher::new(234234543);
@@ -1,77 +1,148 @@
-mod chess;
-mod engine;
-mod prng;
-mod uci;
-mod zobrist;
-
-use crate::chess::{make_move, Board};
-use crate::engine::SearchOptions;
-use crate::zobrist::ZobristHasher;
-use crate::uci::run_uci_mode;
+use chess::chess::{self as board, Board, BoardState, Game};
+use chess::engine::{self, SearchOptions, TranspositionTable};
+use chess::uci::run_uci_mode;
 
+use std::sync::Arc;
 use std::time::Instant;
 
-// fn play_vs_self(board: Option<Board>, options: SearchOptions) {
-//     let mut board = board.unwrap_or_else(|| Board::default());
-
-//     while board.is_live() {
-//         match engine::search(board, options, None, None).expect("No halts = no Err") {
-//             Some(mv) => {
-//                 println!("{}", mv.uci());
-//                 board = make_move(&board, mv);
-//                 println!("{}", board);
-//                 println!("{}", board.get_fen());
-//             },
-//             None => {
-//                 break;
-//             }
-//         }
-//     }
-//     match board.get_state() {
-//         BoardState::WhiteWin => println!("white wins!"),
-//         BoardState::BlackWin => println!("black wins!"),
-//         BoardState::Stalemate => println!("stalemate"),
-//         BoardState::ThreefoldRepetition => println!("threefold repetition"),
-//         BoardState::FiftyMoveRule => println!("fifty move rule"),
-//         BoardState::InsufficientMaterial => println!("insufficient material"),
-//         BoardState::Live => unreachable!()
-//     };
-
-//     println!("{}", board.get_fen());
-// }
-
-fn get_input(msg: &str) -> String {
-    println!("{}", msg);
-    let mut buf = String::new();
-    std::io::stdin()
-        .read_line(&mut buf)
-        .expect("Failed to read line");
-    buf.trim().to_owned()
-}
+const USAGE: &str = "usage: chess [uci | bench | perft <fen> <depth> | play [fen] | epd <file> <depth>]";
+
+// Time per move when the engine plays itself, in ms
+const PLAY_MOVE_TIME: usize = 1000;
+
+fn play_vs_self(board: Board) {
+    let options = SearchOptions {
+        max_depth: usize::MAX,
+        time: PLAY_MOVE_TIME,
+        nodes: None,
+        hash_mb: engine::DEFAULT_HASH_MB,
+        threads: engine::DEFAULT_THREADS,
+        contempt: engine::DEFAULT_CONTEMPT,
+        ponder: false,
+        skill: engine::MAX_SKILL,
+        seed: 0,
+        multi_pv: 1,
+        eval_params: engine::DEFAULT_EVAL_PARAMS,
+    };
+    let tt = Arc::new(TranspositionTable::new(options.hash_mb));
+    let mut game = Game::from_board(board);
+
+    println!("{}", game.board().to_unicode());
+    while game.state() == BoardState::Live {
+        let result = engine::search(game.board(), options, tt.clone(), None, game.position_history(), None, None)
+            .expect("search can only be quit through a halt receiver");
+        let Some(mv) = result.best_move else { break; };
 
-fn best_move_of_input(options: SearchOptions) {
-    let fen = get_input("Input FEN:");
-    let Some(mut board) = Board::new(fen.as_str()) else { panic!("invalid FEN"); };
-    println!("{}", board);
+        println!("{} ({})", mv.uci(), result.score);
+        game.play(mv);
+        println!("{}", game.board().to_unicode());
+    }
+
+    match game.state() {
+        BoardState::WhiteWin => println!("white wins!"),
+        BoardState::BlackWin => println!("black wins!"),
+        BoardState::Stalemate => println!("stalemate"),
+        BoardState::ThreefoldRepetition => println!("threefold repetition"),
+        BoardState::FiftyMoveRule => println!("fifty move rule"),
+        BoardState::InsufficientMaterial => println!("insufficient material"),
+        BoardState::Live => unreachable!()
+    };
 
+    println!("{}", game.to_fen());
+    print!("{}", game.to_pgn(&[]));
+}
+
+fn run_perft(board: &Board, depth: usize) {
+    // Prints the count after each root move before the total, to compare against another engine's divide
     let start = Instant::now();
+    let divide = engine::perft_divide(board, depth);
+    for (mv, subtotal) in &divide {
+        println!("{}: {}", mv.uci(), subtotal);
+    }
+    let count = if depth == 0 { 1 } else { divide.iter().map(|(_, subtotal)| subtotal).sum() };
+    let elapsed = start.elapsed();
 
-    let best_move = engine::search(&mut board, options, None, None).unwrap();
+    println!();
+    println!("Nodes: {}", count);
+    println!("Time: {:?} ({} nps)", elapsed, (count as f64 / elapsed.as_secs_f64()) as usize);
+}
+
+fn run_epd(path: &str, depth: usize) -> Result<(), String> {
+    // Searches each position of an EPD test suite to a fixed depth, printing which ones were solved
+    let contents = std::fs::read_to_string(path).map_err(|err| format!("couldn't read {}: {}", path, err))?;
+    let epds = contents.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| board::parse_epd(line).ok_or_else(|| format!("invalid EPD: {}", line)))
+        .collect::<Result<Vec<_>, _>>()?;
 
-    println!("Time: {:?}", start.elapsed());
+    let options = SearchOptions {
+        max_depth: depth,
+        time: usize::MAX,
+        nodes: None,
+        hash_mb: engine::DEFAULT_HASH_MB,
+        threads: engine::DEFAULT_THREADS,
+        contempt: engine::DEFAULT_CONTEMPT,
+        ponder: false,
+        skill: engine::MAX_SKILL,
+        seed: 0,
+        multi_pv: 1,
+        eval_params: engine::DEFAULT_EVAL_PARAMS,
+    };
 
-    match best_move {
-        Some(mv) => println!("{}", mv.uci()),
-        None => print!("No moves!")
+    let mut solved = 0;
+    for (i, epd) in epds.iter().enumerate() {
+        let id = epd.id.clone().unwrap_or_else(|| format!("#{}", i + 1));
+        if engine::solves_epd(epd, options) {
+            solved += 1;
+            println!("{}: solved", id);
+        } else {
+            println!("{}: failed", id);
+        }
     }
+    println!("Solved {}/{}", solved, epds.len());
+    Ok(())
 }
 
-pub static ZOBRIST_HASHER: ZobristHasher = ZobristHasher::new(234234543);
+fn parse_board(fen: &str) -> Result<Board, String> {
+    if fen == "startpos" {
+        return Ok(Board::default());
+    }
+    Board::try_from_fen(fen).map_err(|err| format!("invalid FEN: {}", err))
+}
 
-fn main() {
-    chess::init_magic_tables();
-    run_uci_mode();
+fn run_command(args: &[String]) -> Result<(), String> {
+    // Picks the mode from the command line; FENs can be passed as one quoted argument or as separate fields
+    match args.split_first() {
+        None => run_uci_mode(),
+        Some((command, rest)) => match (command.as_str(), rest) {
+            ("uci", []) => run_uci_mode(),
+            ("bench", []) => {
+                let (nodes, nps) = engine::bench();
+                println!("{} nodes {} nps", nodes, nps);
+            },
+            ("perft", [fen @ .., depth]) if !fen.is_empty() => {
+                let board = parse_board(&fen.join(" "))?;
+                let depth = depth.parse().map_err(|_| format!("invalid depth: {}", depth))?;
+                run_perft(&board, depth);
+            },
+            ("epd", [path, depth]) => {
+                let depth = depth.parse().map_err(|_| format!("invalid depth: {}", depth))?;
+                run_epd(path, depth)?;
+            },
+            ("play", []) => play_vs_self(Board::default()),
+            ("play", fen) => play_vs_self(parse_board(&fen.join(" "))?),
+            _ => return Err(USAGE.to_owned())
+        }
+    }
+    Ok(())
 }
 
-// start
-// rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1
\ No newline at end of file
+fn main() {
+    board::init_magic_tables();
+
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if let Err(err) = run_command(&args) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
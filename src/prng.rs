@@ -1,18 +1,59 @@
-pub struct PRNG(u128);
+#[allow(clippy::upper_case_acronyms)]
+pub struct PRNG(u64);
 
 impl PRNG {
     pub const fn new(seed: u128) -> Self {
-        Self(seed)
+        Self((seed ^ (seed >> 64)) as u64)
     }
 
     pub const fn next(&mut self) -> u64 {
-        // Constants from https://en.wikipedia.org/wiki/Linear_congruential_generator#Parameters_in_common_use
-        // self.0 = (self.0 * 6364136223846793005 + 1442695040888963407) & ((1 << 64) - 1);
-        // TODO: improve quality of randomness
-
-        self.0 *= 6364136223846793005;
-        self.0 += 1442695040888963407;
-        self.0 &= (1 << 64) - 1;
-        return self.0 as u64;
+        // splitmix64 (https://prng.di.unimi.it/splitmix64.c), which unlike an LCG has good low bits
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn known_outputs() {
+        // The first outputs of the reference splitmix64 with a seed of 0
+        let mut prng = PRNG::new(0);
+        assert_eq!([prng.next(), prng.next(), prng.next()], [0xE220A8397B1DCDAF, 0x6E789E6AA1B965F4, 0x06C45D188009454F]);
+
+        // The two halves of the seed are folded together
+        assert_eq!(PRNG::new(1 << 64).next(), PRNG::new(1).next());
+    }
+
+    #[test]
+    fn outputs_are_distinct() {
+        let mut prng = PRNG::new(234234543);
+        let outputs = (0..10_000).map(|_| prng.next()).collect::<HashSet<_>>();
+        assert_eq!(outputs.len(), 10_000);
+
+        let firsts = (0..1000).map(|seed| PRNG::new(seed).next()).collect::<HashSet<_>>();
+        assert_eq!(firsts.len(), 1000);
+    }
+
+    #[test]
+    fn low_bits_are_random() {
+        // An LCG's lowest bit just alternates, which would make `next() % n` badly skewed
+        let mut prng = PRNG::new(42);
+        let outputs = (0..4096).map(|_| prng.next()).collect::<Vec<_>>();
+
+        let mut counts = [0; 4];
+        for output in &outputs {
+            counts[(output % 4) as usize] += 1;
+        }
+        assert!(counts.iter().all(|&count| (896..1152).contains(&count)), "{:?}", counts);
+
+        let repeats = outputs.windows(2).filter(|pair| pair[0] & 1 == pair[1] & 1).count();
+        assert!((1792..2304).contains(&repeats), "{}", repeats);
+    }
+}
@@ -1,10 +1,16 @@
-use crate::{chess::{self, Board, Move, Piece, Square, START_POS_FEN}, engine};
+use crate::{book::Book, chess::{Board, Move, Piece, Square, START_POS_FEN}, engine::{self, EngineConfig, SearchResult, TranspositionTable}};
 
-use std::{sync::mpsc, thread};
+use std::{sync::{mpsc, Arc}, thread, time::{SystemTime, UNIX_EPOCH}};
+
+// Sent as the best move when there isn't one, e.g. when checkmated
+const NULL_MOVE: &str = "0000";
 
 #[derive(Debug, PartialEq)]
 enum UciCommand {
     Uci,
+    Debug {
+        on: bool
+    },
     SetOption {
         option: UciOption
     },
@@ -18,17 +24,27 @@ enum UciCommand {
         options: UciGoOptions
     },
     Stop,
+    PonderHit,
     Quit,
+    // Non-standard
+    Bench,
 }
 
 pub enum HaltCommand {
     Stop,
+    PonderHit,
     Quit
 }
 
 #[derive(Debug, PartialEq)]
 enum UciOption {
-
+    Hash(usize),
+    Threads(usize),
+    Contempt(isize),
+    SkillLevel(u8),
+    MultiPv(usize),
+    OwnBook(bool),
+    BookFile(String),
 }
 
 #[derive(Debug, PartialEq)]
@@ -51,7 +67,10 @@ pub struct UciGoOptions {
 pub enum UciResponse {
     Uci,
     IsReady,
-    BestMove(String),
+    BestMove {
+        best_move: String,
+        ponder: Option<String>
+    },
     Plaintext(String),
 }
 
@@ -72,6 +91,9 @@ pub fn run_uci_mode() {
             if let Some(command) = parse_uci_command(&buf) {
                 match command {
                     UciCommand::Stop => halt_sender.send(HaltCommand::Stop).expect("stdin error"),
+                    UciCommand::PonderHit => halt_sender.send(HaltCommand::PonderHit).expect("stdin error"),
+                    // A new position means the ponder move wasn't played, so the search is stopped and restarted
+                    UciCommand::Position { .. } => halt_sender.send(HaltCommand::Stop).expect("stdin error"),
                     UciCommand::Quit => halt_sender.send(HaltCommand::Quit).expect("stdin error"),
                     _ => {}
                 };
@@ -87,13 +109,21 @@ pub fn run_uci_mode() {
                 UciResponse::Uci => {
                     println!("id name ElleBot");
                     println!("id author Elle");
+                    println!("option name Hash type spin default {} min 1 max {}", engine::DEFAULT_HASH_MB, engine::MAX_HASH_MB);
+                    println!("option name Threads type spin default {} min 1 max {}", engine::DEFAULT_THREADS, engine::MAX_THREADS);
+                    println!("option name Contempt type spin default {} min {} max {}", engine::DEFAULT_CONTEMPT, -engine::MAX_CONTEMPT, engine::MAX_CONTEMPT);
+                    println!("option name Skill Level type spin default {} min 0 max {}", engine::MAX_SKILL, engine::MAX_SKILL);
+                    println!("option name MultiPV type spin default {} min 1 max {}", engine::DEFAULT_MULTI_PV, engine::MAX_MULTI_PV);
+                    println!("option name OwnBook type check default false");
+                    println!("option name BookFile type string default <empty>");
                     println!("uciok");
                 },
                 UciResponse::IsReady => {
                     println!("readyok");
                 },
-                UciResponse::BestMove(mv) => {
-                    println!("bestmove {}", mv);
+                UciResponse::BestMove { best_move, ponder } => match ponder {
+                    Some(ponder) => println!("bestmove {} ponder {}", best_move, ponder),
+                    None => println!("bestmove {}", best_move)
                 },
                 UciResponse::Plaintext(text) => {
                     println!("{}", text);
@@ -103,34 +133,79 @@ pub fn run_uci_mode() {
     });
 
     let mut board = Board::default();
+    // Hashes of the positions before `board` in the current game
+    let mut history = Vec::new();
+    // Seeded from the clock so that weakened play differs between sessions
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_nanos() as u64);
+    let mut config = EngineConfig { seed, ..EngineConfig::default() };
+    // Kept between searches, and only cleared for a new game
+    let mut tt = Arc::new(TranspositionTable::new(config.hash_mb));
+    // Whether to send extra `info string` lines, off unless the GUI asks for them
+    let mut debug = false;
+    // The opening book from `BookFile`, only played from when `OwnBook` is on
+    let mut book = None;
+    let mut own_book = false;
 
     for command in stdin_receiver {
         match command {
             UciCommand::Uci => {
                 stdout_sender.send(UciResponse::Uci).expect("stdout error");
             },
+            UciCommand::Debug { on } => {
+                debug = on;
+            },
             UciCommand::SetOption { option } => {
-                todo!()
+                match option {
+                    UciOption::Hash(mb) => {
+                        config.hash_mb = mb.clamp(1, engine::MAX_HASH_MB);
+                        tt = Arc::new(TranspositionTable::new(config.hash_mb));
+                    },
+                    UciOption::Threads(threads) => config.threads = threads.clamp(1, engine::MAX_THREADS),
+                    UciOption::Contempt(contempt) => config.contempt = contempt.clamp(-engine::MAX_CONTEMPT, engine::MAX_CONTEMPT),
+                    UciOption::SkillLevel(skill) => config.skill = skill.min(engine::MAX_SKILL),
+                    UciOption::MultiPv(lines) => config.multi_pv = lines.clamp(1, engine::MAX_MULTI_PV),
+                    UciOption::OwnBook(on) => own_book = on,
+                    UciOption::BookFile(path) => {
+                        book = None;
+                        if path.is_empty() || path == "<empty>" { continue; }
+                        match Book::open(&path) {
+                            Ok(new) => {
+                                if debug { send_info_string(&stdout_sender, format!("loaded {} book entries from {}", new.len(), path)); }
+                                book = Some(new);
+                            },
+                            Err(err) => send_info_string(&stdout_sender, format!("could not open book {}: {}", path, err))
+                        }
+                    },
+                }
             },
             UciCommand::Position { fen, moves } => {
-                board = match Board::new(&fen) {
-                    Some(new) => new,
-                    None => return
+                // Bad input is reported rather than crashing: an invalid FEN leaves the position as it was,
+                // and the moves stop being played at the first illegal one
+                let Some(new) = Board::new(&fen) else {
+                    send_info_string(&stdout_sender, format!("invalid fen {}", fen));
+                    continue;
                 };
+                board = new;
 
-                for mv in moves {
-                    board = chess::make_move(&board, Move::from_uci(&mv, &board).unwrap());
+                history.clear();
+                let moves = moves.iter().map(String::as_str).collect::<Vec<_>>();
+                if let Err(i) = board.apply_uci_moves_with_history(&moves, &mut history) {
+                    send_info_string(&stdout_sender, format!("illegal move {}", moves[i]));
                 }
-                // println!("debug: set position to {}", board.get_fen());
+                if debug { send_info_string(&stdout_sender, format!("set position to {}", board.get_fen())); }
             },
             UciCommand::UciNewGame => {
-
+                // GUIs send this before each game's first `position`, so nothing carries over from the last game.
+                // Some don't send it at all, in which case the transposition table just keeps filling up.
+                tt.clear();
+                board = Board::default();
+                history.clear();
             },
             UciCommand::IsReady => {
                 stdout_sender.send(UciResponse::IsReady).expect("stdout error");
             },
             UciCommand::Go { options } => {
-                println!("debug: received GoOptions {:?}", options);
+                if debug { send_info_string(&stdout_sender, format!("received GoOptions {:?}", options)); }
 
                 // Clear any previous 'stop' commands
                 while halt_receiver.try_recv().is_ok() {};
@@ -140,57 +215,124 @@ pub fn run_uci_mode() {
                     .collect()
                 );
 
-                if options.infinite {
-                    println!("debug: searching infinitely");
-                    let Ok(Some(best_move)) = engine::search_infinite(&mut board, search_moves, &halt_receiver) else { return; };
-                    stdout_sender.send(UciResponse::BestMove(best_move.uci())).expect("stdout error");
+                // In-book positions are answered straight away, unless the search is for something other than a move to play
+                let book_move = book.as_ref()
+                    .filter(|_| own_book && !options.infinite && !options.ponder && options.perft.is_none() && options.mate.is_none() && search_moves.is_none())
+                    .and_then(|book| book.pick(&board, config.seed ^ board.get_hash()));
+
+                if let Some(book_move) = book_move {
+                    if debug { send_info_string(&stdout_sender, "playing a book move".to_owned()); }
+                    stdout_sender.send(UciResponse::BestMove { best_move: book_move.uci(), ponder: None }).expect("stdout error");
+                }
+
+                else if options.infinite {
+                    if debug { send_info_string(&stdout_sender, "searching infinitely".to_owned()); }
+                    let search_options = engine::decide_options(&board, &options, &config);
+                    let Ok(result) = engine::search_infinite(&board, search_options, tt.clone(), search_moves, &history, &halt_receiver, Some(&stdout_sender)) else { return; };
+                    stdout_sender.send(best_move_response(&result)).expect("stdout error");
                 }
 
                 else if let Some(depth) = options.perft {
-                    println!("debug: running perft test with depth {}", depth);
+                    if debug { send_info_string(&stdout_sender, format!("running perft test with depth {}", depth)); }
                     let count = engine::search_perft(&board, depth, Some(&stdout_sender));
                     stdout_sender.send(UciResponse::Plaintext(count.to_string())).expect("stdout error");
                 }
 
+                // Mate searches don't ponder
+                else if let Some(mate) = options.mate.filter(|&mate| mate > 0 && !options.ponder) {
+                    if debug { send_info_string(&stdout_sender, format!("searching for mate in {}", mate)); }
+                    let response = match engine::search_mate(&board, mate, search_moves.clone(), Some(&halt_receiver), Some(&stdout_sender)) {
+                        Ok(Some(best_move)) => UciResponse::BestMove { best_move: best_move.uci(), ponder: None },
+                        // No forced mate, so fall back to a regular search
+                        Ok(None) => {
+                            let search_options = engine::decide_options(&board, &options, &config);
+                            let Ok(result) = engine::search(&board, search_options, tt.clone(), search_moves, &history, Some(&halt_receiver), Some(&stdout_sender)) else { return; };
+                            best_move_response(&result)
+                        },
+                        Err(()) => return
+                    };
+                    stdout_sender.send(response).expect("stdout error");
+                }
+
                 else {
-                    let search_options = engine::decide_options(&mut board, &options);
-                    println!("debug: decided search options {:?}", search_options);
-                    let Ok(Some(best_move)) = engine::search(&mut board, search_options, search_moves, Some(&halt_receiver)) else { return; };
-                    stdout_sender.send(UciResponse::BestMove(best_move.uci())).expect("stdout error");
+                    let search_options = engine::decide_options(&board, &options, &config);
+                    if debug { send_info_string(&stdout_sender, format!("decided search options {:?}", search_options)); }
+                    let Ok(result) = engine::search(&board, search_options, tt.clone(), search_moves, &history, Some(&halt_receiver), Some(&stdout_sender)) else { return; };
+                    stdout_sender.send(best_move_response(&result)).expect("stdout error");
                 }
             },
-            UciCommand::Stop => {
+            UciCommand::Stop | UciCommand::PonderHit => {
 
             },
             UciCommand::Quit => {
                 return;
             },
+            UciCommand::Bench => {
+                let (nodes, nps) = engine::bench();
+                stdout_sender.send(UciResponse::Plaintext(format!("{} nodes {} nps", nodes, nps))).expect("stdout error");
+            },
         };
     }
 }
 
+fn send_info_string(stdout_sender: &mpsc::Sender<UciResponse>, text: String) {
+    // Free-form text for the GUI to show
+    stdout_sender.send(UciResponse::Plaintext(format!("info string {}", text))).expect("stdout error");
+}
+
+fn best_move_response(result: &SearchResult) -> UciResponse {
+    // The second move of the principal variation is the reply to ponder on
+    let Some(best_move) = result.best_move else {
+        return UciResponse::BestMove { best_move: NULL_MOVE.to_owned(), ponder: None };
+    };
+    let ponder = result.pv.get(1).filter(|_| result.pv.first() == Some(&best_move));
+    UciResponse::BestMove { best_move: best_move.uci(), ponder: ponder.map(|mv| mv.uci()) }
+}
+
 fn parse_uci_command(command: &str) -> Option<UciCommand> {
     let mut words = command.split_whitespace();
 
     match words.next()? {
         "uci" => Some(UciCommand::Uci),
+        "debug" => match words.next()? {
+            "on" => Some(UciCommand::Debug { on: true }),
+            "off" => Some(UciCommand::Debug { on: false }),
+            _ => None
+        },
         "setoption" => {
-            todo!()
+            // setoption name <id> [value <x>]
+            if words.next()? != "name" { return None; }
+            let name = (&mut words).take_while(|&word| word != "value").collect::<Vec<_>>().join(" ");
+            let value = words.collect::<Vec<_>>().join(" ");
+
+            let option = match name.to_ascii_lowercase().as_str() {
+                "hash" => UciOption::Hash(value.parse().ok()?),
+                "threads" => UciOption::Threads(value.parse().ok()?),
+                "contempt" => UciOption::Contempt(value.parse().ok()?),
+                "skill level" => UciOption::SkillLevel(value.parse().ok()?),
+                "multipv" => UciOption::MultiPv(value.parse().ok()?),
+                "ownbook" => UciOption::OwnBook(value.parse().ok()?),
+                "bookfile" => UciOption::BookFile(value),
+                _ => return None
+            };
+
+            Some(UciCommand::SetOption { option })
         },
         "position" => {
+            // position (startpos | fen <fen>) [moves <move>...]
+            // FENs can leave out the move counters, so the FEN runs until `moves` or the end of the line
             let fen = match words.next()? {
-                "startpos" => START_POS_FEN.to_owned(),
-                "fen" => (&mut words).take(6).collect::<Vec<&str>>().join(" "),
+                "startpos" => {
+                    if words.next() != Some("moves") {
+                        return Some(UciCommand::Position { fen: START_POS_FEN.to_owned(), moves: Vec::new() });
+                    }
+                    START_POS_FEN.to_owned()
+                },
+                "fen" => (&mut words).take_while(|&word| word != "moves").collect::<Vec<&str>>().join(" "),
                 _ => return None
             };
 
-            let mut moves = Vec::new();
-            if let Some(next) = words.next() {
-                if next != "moves" {
-                    return Some(UciCommand::Position { fen, moves: Vec::new() });
-                }
-                moves = words.map(|str| str.to_owned()).collect();
-            }
+            let moves = words.map(|str| str.to_owned()).collect();
 
             Some(UciCommand::Position { fen, moves })
         },
@@ -262,7 +404,9 @@ fn parse_uci_command(command: &str) -> Option<UciCommand> {
             Some(UciCommand::Go { options })
         },
         "stop" => Some(UciCommand::Stop),
+        "ponderhit" => Some(UciCommand::PonderHit),
         "quit" => Some(UciCommand::Quit),
+        "bench" => Some(UciCommand::Bench),
         _ => None
     }
 }
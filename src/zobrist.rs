@@ -1,13 +1,16 @@
-use crate::chess::{Board, COLORS, NUM_COLORS, NUM_FILES, NUM_PIECES, NUM_SQUARES, PIECES};
+use crate::chess::{Board, Castles, Color, Piece, Square, COLORS, NUM_COLORS, NUM_FILES, NUM_PIECES, NUM_SQUARES};
 use crate::prng::PRNG;
 
 const NUM_CASTLES: usize = 16;
+const NUM_CASTLE_ROOKS: usize = 4;
 
 pub struct ZobristHasher {
     pieces: [[[u64; NUM_SQUARES]; NUM_PIECES]; NUM_COLORS],
     side_to_move: u64,
     castles: [u64; NUM_CASTLES],
     en_passant: [u64; NUM_FILES],
+    // File of each castle's rook, left at zero for the standard corner so standard positions hash the same
+    castle_rooks: [[u64; NUM_FILES]; NUM_CASTLE_ROOKS],
 }
 
 impl ZobristHasher {
@@ -48,7 +51,50 @@ impl ZobristHasher {
             i += 1;
         }
 
-        Self { pieces, side_to_move, castles, en_passant }
+        let mut castle_rooks = [[0; NUM_FILES]; NUM_CASTLE_ROOKS];
+
+        let mut i = 0;
+        while i < NUM_CASTLE_ROOKS {
+            // Kingside castles have even indices, with the rook starting on the h-file
+            let corner = if i % 2 == 0 { NUM_FILES - 1 } else { 0 };
+            let mut j = 0;
+            while j < NUM_FILES {
+                if j != corner {
+                    castle_rooks[i][j] = prng.next();
+                }
+                j += 1;
+            }
+            i += 1;
+        }
+
+        Self { pieces, side_to_move, castles, en_passant, castle_rooks }
+    }
+
+    #[inline]
+    pub fn piece_key(&self, piece: Piece, color: Color, square: Square) -> u64 {
+        self.pieces[color.idx()][piece.idx()][square.idx()]
+    }
+
+    #[inline]
+    pub fn side_to_move_key(&self) -> u64 {
+        self.side_to_move
+    }
+
+    #[inline]
+    pub fn castles_key(&self, castles: Castles, castle_rooks: [Square; NUM_CASTLE_ROOKS]) -> u64 {
+        // `castle_rooks` is indexed like the bits of `castles`, and only the rooks of allowed castles are hashed
+        let mut key = self.castles[castles.idx()];
+        for (i, rook) in castle_rooks.into_iter().enumerate() {
+            if castles.idx() & (1 << i) != 0 {
+                key ^= self.castle_rooks[i][rook.file().idx()];
+            }
+        }
+        key
+    }
+
+    #[inline]
+    pub fn en_passant_key(&self, en_passant: Square) -> u64 {
+        self.en_passant[en_passant.file().idx()]
     }
 
     pub fn hash(&self, board: &Board) -> u64 {
@@ -56,10 +102,8 @@ impl ZobristHasher {
 
         // Pieces
         for color in COLORS {
-            for piece in PIECES {
-                for square in board.get_color(color) & board.get_piece(piece) {
-                    hash ^= self.pieces[color.idx()][piece.idx()][square.idx()];
-                }
+            for (square, piece) in board.piece_list(color) {
+                hash ^= self.pieces[color.idx()][piece.idx()][square.idx()];
             }
         }
 
@@ -68,11 +112,16 @@ impl ZobristHasher {
             hash ^= self.side_to_move;
         }
 
-        // Castling
+        // Castling, along with the rook of each allowed castle
         hash ^= self.castles[board.get_castles().idx()];
+        for (i, rook) in board.get_castle_rooks().into_iter().enumerate() {
+            if let Some(rook) = rook {
+                hash ^= self.castle_rooks[i][rook.file().idx()];
+            }
+        }
 
-        // En passant
-        if let Some(c) = board.get_en_passant() {
+        // En passant, only if it can be captured
+        if let Some(c) = board.effective_en_passant() {
             hash ^= self.en_passant[c.file().idx()];
         } 
 
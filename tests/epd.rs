@@ -0,0 +1,31 @@
+use chess::chess::{init_magic_tables, parse_epd};
+use chess::engine::{self, SearchOptions};
+
+// The first ten positions of Win at Chess (https://www.chessprogramming.org/Win_at_Chess)
+const WAC: &str = include_str!("data/wac.epd");
+
+const DEPTH: usize = 4;
+const MIN_SOLVED: usize = 8;
+
+#[test]
+fn solves_wac_positions() {
+    init_magic_tables();
+    let epds = WAC.lines().map(|line| parse_epd(line).expect("bundled positions are valid")).collect::<Vec<_>>();
+    assert_eq!(epds.len(), 10);
+
+    let options = SearchOptions {
+        max_depth: DEPTH,
+        time: usize::MAX,
+        nodes: None,
+        hash_mb: engine::DEFAULT_HASH_MB,
+        threads: 1,
+        contempt: engine::DEFAULT_CONTEMPT,
+        ponder: false,
+        skill: engine::MAX_SKILL,
+        seed: 0,
+        multi_pv: 1,
+        eval_params: engine::DEFAULT_EVAL_PARAMS,
+    };
+    let solved = engine::run_epd_suite(&epds, options);
+    assert!(solved >= MIN_SOLVED, "solved {}/{} at depth {}", solved, epds.len(), DEPTH);
+}